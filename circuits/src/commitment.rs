@@ -0,0 +1,136 @@
+//!
+//! The single public input commitment: `rescue(keccak(compressed_data), rescue(data))`.
+//!
+//! The uncompressed data is padded with zeroes to the capacity and packed by
+//! `COMMITMENT_CHUNK_BYTES` bytes(big endian) into the field elements, the keccak256
+//! of the compressed data is packed into two 128-bit halves. The same packing and
+//! sponge are used in the circuit and out of it.
+//!
+//! The keccak256 of the compressed data is not computed in the circuit: its bytes are
+//! the prover's witness, only range checked. So the commitment binds the uncompressed
+//! data, but not the compressed data, the verifier must not rely on the keccak half.
+//!
+
+use franklin_crypto::{
+    bellman::{
+        compact_bn256::{Bn256, Fr},
+        plonk::better_better_cs::cs::ConstraintSystem,
+        Engine, Field, SynthesisError,
+    },
+    plonk::circuit::{allocated_num::Num, byte::Byte, linear_combination::LinearCombination},
+};
+use rescue_poseidon::{CircuitGenericSponge, GenericSponge, RescueParams};
+use compression::StorageTransition;
+use compression::sha3;
+use compression::sha3::Digest;
+
+use crate::main_circuit::{Capacity, PACKED_INPUT_BYTES};
+use crate::utils::fr_from_usize;
+
+/// The number of the data bytes packed into one field element, 31 bytes fit the field.
+pub const COMMITMENT_CHUNK_BYTES: usize = 31;
+
+///
+/// Pack the bytes by `chunk_size` in big endian into the field elements.
+///
+pub fn pack_bytes<E: Engine>(bytes: &[u8], chunk_size: usize) -> Vec<E::Fr> {
+    let base = fr_from_usize::<E::Fr>(256);
+    bytes
+        .chunks(chunk_size)
+        .map(|chunk| {
+            chunk.iter().fold(E::Fr::zero(), |mut value, byte| {
+                value.mul_assign(&base);
+                value.add_assign(&fr_from_usize(*byte as usize));
+                value
+            })
+        })
+        .collect()
+}
+
+///
+/// Pack the allocated bytes by `chunk_size` in big endian, the same as `pack_bytes`.
+///
+pub fn circuit_pack_bytes<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, bytes: &[Byte<E>], chunk_size: usize) -> Result<Vec<Num<E>>, SynthesisError> {
+    let base = fr_from_usize::<E::Fr>(256);
+    let mut result = Vec::with_capacity((bytes.len() + chunk_size - 1) / chunk_size);
+    for chunk in bytes.chunks(chunk_size) {
+        let mut lc = LinearCombination::zero();
+        let mut coefficient = E::Fr::one();
+        for byte in chunk.iter().rev() {
+            lc.add_assign_number_with_coeff(&byte.inner, coefficient);
+            coefficient.mul_assign(&base);
+        }
+        result.push(lc.into_num(cs)?);
+    }
+
+    Ok(result)
+}
+
+///
+/// The commitment to the uncompressed data padded to the capacity.
+///
+pub fn data_commitment(capacity: Capacity, data: &[u8]) -> Fr {
+    let mut padded = data.to_vec();
+    padded.resize(capacity.max_uncompressed_data_size(), 0);
+
+    let params = RescueParams::<Bn256, 2, 3>::default();
+    let mut sponge = GenericSponge::<Bn256, 2, 3>::new();
+    sponge.absorb_multiple(&pack_bytes::<Bn256>(&padded, COMMITMENT_CHUNK_BYTES), &params);
+    sponge.pad_if_necessary();
+    sponge.squeeze(&params).expect("Always valid after padding")
+}
+
+///
+/// The single public input for the uncompressed and compressed data.
+///
+pub fn public_input_commitment(capacity: Capacity, data: &[u8], compressed_data: &[u8]) -> Fr {
+    let compressed_data_hash = sha3::Keccak256::digest(compressed_data);
+    let mut elements = pack_bytes::<Bn256>(compressed_data_hash.as_slice(), PACKED_INPUT_BYTES);
+    elements.push(data_commitment(capacity, data));
+
+    let params = RescueParams::<Bn256, 2, 3>::default();
+    let mut sponge = GenericSponge::<Bn256, 2, 3>::new();
+    sponge.absorb_multiple(&elements, &params);
+    sponge.pad_if_necessary();
+    sponge.squeeze(&params).expect("Always valid after padding")
+}
+
+///
+/// The single public input for the storage transitions batch.
+///
+pub fn transitions_commitment(capacity: Capacity, transitions: &[StorageTransition]) -> Fr {
+    public_input_commitment(
+        capacity,
+        &StorageTransition::into_bytes(transitions.to_vec()),
+        &StorageTransition::compress(transitions.to_vec()),
+    )
+}
+
+///
+/// Compute `public_input_commitment` in the circuit, the data bytes are padded to the capacity.
+/// `compressed_data_hash` is absorbed as is, nothing proves it's the keccak256 of the compressed data.
+///
+pub fn circuit_public_input_commitment<E: Engine, CS: ConstraintSystem<E>>(
+    cs: &mut CS,
+    data: &[Byte<E>],
+    compressed_data_hash: &[Byte<E>],
+) -> Result<Num<E>, SynthesisError> {
+    let params = RescueParams::<E, 2, 3>::default();
+
+    let mut sponge = CircuitGenericSponge::<E, 2, 3>::new();
+    for element in circuit_pack_bytes(cs, data, COMMITMENT_CHUNK_BYTES)? {
+        sponge.absorb(cs, element, &params)?;
+    }
+    sponge.pad_if_necessary();
+    let data_commitment = sponge.squeeze_num(cs, &params)?.expect("Always valid after padding");
+
+    let mut sponge = CircuitGenericSponge::<E, 2, 3>::new();
+    for element in circuit_pack_bytes(cs, compressed_data_hash, PACKED_INPUT_BYTES)? {
+        sponge.absorb(cs, element, &params)?;
+    }
+    sponge.absorb(cs, data_commitment, &params)?;
+    sponge.pad_if_necessary();
+    let commitment = sponge.squeeze_num(cs, &params)?.expect("Always valid after padding");
+
+    Ok(commitment)
+}
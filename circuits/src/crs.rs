@@ -0,0 +1,158 @@
+//!
+//! The monomial form CRS loading.
+//!
+//! Supported formats:
+//! - franklin `Crs::write` format;
+//! - snarkjs/Hermez powers of tau `.ptau` files(sections 1, 2 and 3 are used).
+//!
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use franklin_crypto::bellman::{
+    compact_bn256::{Bn256, Fq, Fq2, FqRepr, G1Affine, G2Affine},
+    kate_commitment::{Crs, CrsForMonomialForm},
+    CurveAffine, Field, PrimeField, PrimeFieldRepr,
+};
+#[cfg(any(test, feature = "insecure-test-crs"))]
+use franklin_crypto::bellman::worker::Worker;
+
+use crate::error::Error;
+
+/// The size of the field element in the `.ptau` file.
+const PTAU_FIELD_SIZE: u32 = 32;
+const PTAU_HEADER_SECTION: u32 = 1;
+const PTAU_TAU_G1_SECTION: u32 = 2;
+const PTAU_TAU_G2_SECTION: u32 = 3;
+
+///
+/// Where to take the CRS from.
+///
+#[derive(Debug, Clone)]
+pub enum CrsSource {
+    /// The file in the franklin `Crs::write` format.
+    Franklin(PathBuf),
+    /// The snarkjs/Hermez powers of tau file.
+    Ptau(PathBuf),
+    /// The CRS with the known toxic waste(`Crs::crs_42`), every proof made with it can be forged.
+    #[cfg(any(test, feature = "insecure-test-crs"))]
+    InsecureTest,
+}
+
+impl CrsSource {
+    ///
+    /// Load the CRS for the domain size, fails if the CRS is too small.
+    ///
+    pub fn load(&self, domain_size: usize) -> Result<Crs<Bn256, CrsForMonomialForm>, Error> {
+        match self {
+            CrsSource::Franklin(path) => {
+                let crs = Crs::<Bn256, CrsForMonomialForm>::read(BufReader::new(File::open(path)?))?;
+                if crs.g1_bases.len() < domain_size || crs.g2_monomial_bases.len() < 2 {
+                    return Err(Error::CrsTooSmall {
+                        required: domain_size,
+                        available: crs.g1_bases.len(),
+                    });
+                }
+                Ok(crs)
+            },
+            CrsSource::Ptau(path) => read_ptau(BufReader::new(File::open(path)?), domain_size),
+            #[cfg(any(test, feature = "insecure-test-crs"))]
+            CrsSource::InsecureTest => Ok(Crs::<Bn256, CrsForMonomialForm>::crs_42(domain_size, &Worker::new())),
+        }
+    }
+}
+
+///
+/// Read the first `domain_size` powers of tau in G1 and two in G2 from the `.ptau` file.
+///
+pub fn read_ptau<R: Read + Seek>(mut reader: R, domain_size: usize) -> Result<Crs<Bn256, CrsForMonomialForm>, Error> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"ptau" {
+        return Err(Error::InvalidFormat("invalid ptau magic".to_owned()));
+    }
+    let _version = read_u32(&mut reader)?;
+
+    let sections_count = read_u32(&mut reader)?;
+    let mut sections = HashMap::new();
+    for _ in 0..sections_count {
+        let id = read_u32(&mut reader)?;
+        let size = read_u64(&mut reader)?;
+        let position = reader.stream_position()?;
+        sections.insert(id, (position, size));
+        reader.seek(SeekFrom::Start(position + size))?;
+    }
+    let mut seek_section = |reader: &mut R, id: u32, min_size: u64| -> Result<(), Error> {
+        let (position, size) = sections
+            .remove(&id)
+            .ok_or_else(|| Error::InvalidFormat(format!("ptau section {} is missing", id)))?;
+        if size < min_size {
+            return Err(Error::InvalidFormat(format!("ptau section {} is too small", id)));
+        }
+        reader.seek(SeekFrom::Start(position))?;
+        Ok(())
+    };
+
+    seek_section(&mut reader, PTAU_HEADER_SECTION, 4 + PTAU_FIELD_SIZE as u64 + 8)?;
+    if read_u32(&mut reader)? != PTAU_FIELD_SIZE {
+        return Err(Error::InvalidFormat("ptau field size is not 32 bytes".to_owned()));
+    }
+    let mut modulus = FqRepr::default();
+    modulus.read_le(&mut reader)?;
+    if modulus != Fq::char() {
+        return Err(Error::InvalidFormat("ptau is not for the bn254 curve".to_owned()));
+    }
+    let power = read_u32(&mut reader)?;
+    if power >= usize::BITS || domain_size > 1usize << power {
+        return Err(Error::CrsTooSmall {
+            required: domain_size,
+            available: 1usize.checked_shl(power).unwrap_or(usize::MAX),
+        });
+    }
+
+    seek_section(&mut reader, PTAU_TAU_G1_SECTION, domain_size as u64 * 2 * PTAU_FIELD_SIZE as u64)?;
+    let mut g1_bases = Vec::with_capacity(domain_size);
+    for _ in 0..domain_size {
+        let x = read_fq(&mut reader)?;
+        let y = read_fq(&mut reader)?;
+        g1_bases.push(if x.is_zero() && y.is_zero() {
+            G1Affine::zero()
+        } else {
+            G1Affine::from_xy_checked(x, y).map_err(|error| Error::InvalidFormat(error.to_string()))?
+        });
+    }
+
+    seek_section(&mut reader, PTAU_TAU_G2_SECTION, 2 * 4 * PTAU_FIELD_SIZE as u64)?;
+    let mut g2_bases = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let x = Fq2 { c0: read_fq(&mut reader)?, c1: read_fq(&mut reader)? };
+        let y = Fq2 { c0: read_fq(&mut reader)?, c1: read_fq(&mut reader)? };
+        g2_bases.push(G2Affine::from_xy_checked(x, y).map_err(|error| Error::InvalidFormat(error.to_string()))?);
+    }
+
+    Ok(Crs::new(g1_bases, g2_bases))
+}
+
+///
+/// Read the field element in the little endian Montgomery form.
+///
+fn read_fq<R: Read>(reader: &mut R) -> Result<Fq, Error> {
+    let mut repr = FqRepr::default();
+    repr.read_le(reader)?;
+
+    Fq::from_raw_repr(repr).map_err(|error| Error::InvalidFormat(error.to_string()))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
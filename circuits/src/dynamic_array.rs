@@ -0,0 +1,299 @@
+//!
+//! The byte array with reads by dynamic index.
+//!
+//! Reads are not proven one by one, they are recorded into the access log and
+//! proven all together in `enforce_consistency` by one of the `ReadProof`s:
+//! - the linear scan: every read is `sum(bytes[i] * (index == i))`, O(n) gates per read;
+//! - the memory-consistency argument: the initial entries `(i, bytes[i])` and the reads `(index, value)`
+//!   are sorted by index, the sorted log is a permutation of the unsorted one(grand product over rescue
+//!   challenges) and neighbour entries of the sorted log have the same index and value or the next index.
+//!   Every read costs O(1) gates, but the challenges absorb both logs, that's O(n + reads) rescue rounds.
+//!
+//! The rescue absorption makes the memory-consistency argument more expensive for the small arrays(e.g.
+//! the single transition capacity), so `enforce_consistency` counts the gates of both proofs on a dry run
+//! and uses the cheaper one. `dynamic_array_gates_benchmark` prints the comparison.
+//!
+
+use franklin_crypto::{
+    bellman::{
+        Engine, Field, SynthesisError,
+        plonk::better_better_cs::cs::{ConstraintSystem, PlonkCsWidth4WithNextStepParams, SetupAssembly, Width4MainGateWithDNext},
+    },
+    plonk::circuit::{
+        allocated_num::Num,
+        byte::Byte,
+        linear_combination::LinearCombination,
+    },
+};
+use rescue_poseidon::{CircuitGenericSponge, RescueParams};
+
+use crate::utils::{fr_from_usize, fr_to_usize};
+
+///
+/// The access log entry.
+///
+#[derive(Clone)]
+struct Access<E: Engine> {
+    index: Num<E>,
+    value: Num<E>,
+}
+
+///
+/// How `enforce_consistency` proves the recorded reads.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadProof {
+    /// Every read is scanned over the whole array: O(n) gates per read.
+    LinearScan,
+    /// The memory-consistency argument: O(1) gates per read plus O(n + reads) rescue rounds.
+    AccessLog,
+}
+
+///
+/// The byte array gadget with the reads by `Num` index proven all together.
+///
+pub struct DynamicByteArray<E: Engine> {
+    bytes: Vec<Byte<E>>,
+    accesses: Vec<Access<E>>,
+}
+
+impl<E: Engine> DynamicByteArray<E> {
+    pub fn new(bytes: Vec<Byte<E>>) -> Self {
+        Self {
+            bytes,
+            accesses: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    ///
+    /// Read byte by dynamic index, the read is proven only by `enforce_consistency`.
+    ///
+    pub fn read<CS: ConstraintSystem<E>>(&mut self, cs: &mut CS, index: &Num<E>) -> Result<Byte<E>, SynthesisError> {
+        let witness = index.get_value().and_then(|index| {
+            match self.bytes.get(fr_to_usize(index)) {
+                Some(byte) => byte.inner.get_value(),
+                // Out of bounds read can't be proven, any value is fine here.
+                None => Some(E::Fr::zero()),
+            }
+        });
+        let value = Num::alloc(cs, witness)?;
+
+        self.accesses.push(Access {
+            index: index.clone(),
+            value: value.clone(),
+        });
+
+        Ok(Byte { inner: value })
+    }
+
+    ///
+    /// Read `len` bytes starting from the dynamic position.
+    ///
+    pub fn read_word<CS: ConstraintSystem<E>>(&mut self, cs: &mut CS, pos: &Num<E>, len: usize) -> Result<Vec<Byte<E>>, SynthesisError> {
+        let mut result = Vec::with_capacity(len);
+        for offset in 0..len {
+            let index = pos.add(cs, &Num::Constant(fr_from_usize(offset)))?;
+            result.push(self.read(cs, &index)?);
+        }
+
+        Ok(result)
+    }
+
+    ///
+    /// Prove that all recorded reads return the array values with the cheapest `ReadProof`.
+    /// Reads from the empty array can't be proven, they are `SynthesisError::Unsatisfiable`.
+    ///
+    pub fn enforce_consistency<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        if self.accesses.is_empty() {
+            return Ok(());
+        }
+        if self.bytes.is_empty() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let proof = self.cheapest_proof()?;
+        self.enforce_consistency_with(cs, proof)
+    }
+
+    ///
+    /// The proof with the fewer gates for the array length and the recorded reads.
+    /// The gates are counted on a dry run without witness, so the setup and the proving choose the same proof.
+    ///
+    pub fn cheapest_proof(&self) -> Result<ReadProof, SynthesisError> {
+        let indices: Vec<Num<E>> = self.accesses.iter().map(|access| access.index.clone()).collect();
+
+        // Every linear scan read costs the same for the index kind, so only one read of each kind is counted.
+        let (constants, variables): (Vec<_>, Vec<_>) = indices.iter().partition(|index| matches!(index, Num::Constant(_)));
+        let mut linear_scan = 0;
+        if !constants.is_empty() {
+            let gates = Self::dry_run_gates(self.len(), &[Num::Constant(E::Fr::zero())], ReadProof::LinearScan)?;
+            linear_scan += constants.len() * gates;
+        }
+        if !variables.is_empty() {
+            let gates = Self::dry_run_gates(self.len(), &[variables[0].clone()], ReadProof::LinearScan)?;
+            linear_scan += variables.len() * gates;
+        }
+        let access_log = Self::dry_run_gates(self.len(), &indices, ReadProof::AccessLog)?;
+
+        Ok(if linear_scan <= access_log { ReadProof::LinearScan } else { ReadProof::AccessLog })
+    }
+
+    ///
+    /// Gates count of the proof of the reads by `indices` from `len` bytes without witness.
+    ///
+    fn dry_run_gates(len: usize, indices: &[Num<E>], proof: ReadProof) -> Result<usize, SynthesisError> {
+        let mut cs = SetupAssembly::<E, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>::new();
+        let mut bytes = Vec::with_capacity(len);
+        for _ in 0..len {
+            bytes.push(Byte { inner: Num::alloc(&mut cs, None)? });
+        }
+        let mut array = Self::new(bytes);
+        for index in indices {
+            let index = match index {
+                Num::Constant(index) => Num::Constant(*index),
+                Num::Variable(_) => Num::alloc(&mut cs, None)?,
+            };
+            array.accesses.push(Access {
+                index,
+                value: Num::alloc(&mut cs, None)?,
+            });
+        }
+
+        let start = cs.get_current_step_number();
+        array.enforce_consistency_with(&mut cs, proof)?;
+        Ok(cs.get_current_step_number() - start)
+    }
+
+    ///
+    /// Prove that all recorded reads return the array values with the given proof.
+    ///
+    pub fn enforce_consistency_with<CS: ConstraintSystem<E>>(self, cs: &mut CS, proof: ReadProof) -> Result<(), SynthesisError> {
+        if self.accesses.is_empty() {
+            return Ok(());
+        }
+        if self.bytes.is_empty() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        match proof {
+            ReadProof::LinearScan => self.enforce_linear_scan(cs),
+            ReadProof::AccessLog => self.enforce_access_log(cs),
+        }
+    }
+
+    ///
+    /// Every read value is `sum(bytes[i] * (index == i))` and exactly one `i` is equal to the index.
+    ///
+    fn enforce_linear_scan<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let mut minus_one = E::Fr::one();
+        minus_one.negate();
+
+        for access in &self.accesses {
+            let mut hits = LinearCombination::zero();
+            let mut value = LinearCombination::zero();
+            for (i, byte) in self.bytes.iter().enumerate() {
+                let hit = Num::from_boolean_is(Num::equals(cs, &access.index, &Num::Constant(fr_from_usize(i)))?);
+                hits.add_assign_number_with_coeff(&hit, E::Fr::one());
+                value.add_assign_number_with_coeff(&hit.mul(cs, &byte.inner)?, E::Fr::one());
+            }
+
+            // The index is in bounds.
+            hits.add_assign_number_with_coeff(&Num::Constant(E::Fr::one()), minus_one);
+            hits.enforce_zero(cs)?;
+
+            value.add_assign_number_with_coeff(&access.value, minus_one);
+            value.enforce_zero(cs)?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// The memory-consistency argument over the sorted access log.
+    ///
+    fn enforce_access_log<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let mut log: Vec<Access<E>> = self.bytes
+            .iter()
+            .enumerate()
+            .map(|(index, byte)| Access {
+                index: Num::Constant(fr_from_usize(index)),
+                value: byte.inner.clone(),
+            })
+            .collect();
+        log.extend(self.accesses.iter().cloned());
+
+        let sorted_witness = log
+            .iter()
+            .map(|access| Some((access.index.get_value()?, access.value.get_value()?)))
+            .collect::<Option<Vec<_>>>()
+            .map(|mut entries| {
+                entries.sort_by_key(|(index, _)| fr_to_usize(*index));
+                entries
+            });
+        let mut sorted = Vec::with_capacity(log.len());
+        for position in 0..log.len() {
+            let entry = sorted_witness.as_ref().map(|entries| entries[position]);
+            sorted.push(Access {
+                index: Num::alloc(cs, entry.map(|(index, _)| index))?,
+                value: Num::alloc(cs, entry.map(|(_, value)| value))?,
+            });
+        }
+
+        // The sorted log covers exactly the indices 0..len.
+        sorted[0].index.enforce_equal(cs, &Num::Constant(E::Fr::zero()))?;
+        sorted[sorted.len() - 1].index.enforce_equal(cs, &Num::Constant(fr_from_usize(self.bytes.len() - 1)))?;
+        for pair in sorted.windows(2) {
+            // index delta is 0 or 1
+            let delta = pair[1].index.sub(cs, &pair[0].index)?;
+            let delta_square = delta.mul(cs, &delta)?;
+            delta_square.enforce_equal(cs, &delta)?;
+
+            // the same index => the same value
+            let value_delta = pair[1].value.sub(cs, &pair[0].value)?;
+            let masked_value_delta = value_delta.mul(cs, &delta)?;
+            masked_value_delta.enforce_equal(cs, &value_delta)?;
+        }
+
+        // The challenges depend on both logs, so the prover can't adjust the sorted log to them.
+        let params = RescueParams::<E, 2, 3>::default();
+        let mut sponge = CircuitGenericSponge::<E, 2, 3>::new();
+        for access in log.iter().chain(sorted.iter()) {
+            if let Num::Variable(_) = access.index {
+                sponge.absorb(cs, access.index.clone(), &params)?;
+            }
+            sponge.absorb(cs, access.value.clone(), &params)?;
+        }
+        sponge.pad_if_necessary();
+        let alpha = sponge.squeeze_num(cs, &params)?.expect("Always valid after padding");
+        let beta = sponge.squeeze_num(cs, &params)?.expect("Always valid after padding");
+
+        let log_product = grand_product(cs, &log, &alpha, &beta)?;
+        let sorted_product = grand_product(cs, &sorted, &alpha, &beta)?;
+        log_product.enforce_equal(cs, &sorted_product)?;
+
+        Ok(())
+    }
+}
+
+///
+/// Compute `prod(alpha - index - beta * value)` over the log.
+///
+fn grand_product<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, log: &[Access<E>], alpha: &Num<E>, beta: &Num<E>) -> Result<Num<E>, SynthesisError> {
+    let mut result = Num::Constant(E::Fr::one());
+    for access in log {
+        let mut term = beta.mul(cs, &access.value)?;
+        term = term.add(cs, &access.index)?;
+        term = alpha.sub(cs, &term)?;
+        result = result.mul(cs, &term)?;
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,100 @@
+//!
+//! The proving errors.
+//!
+
+use std::fmt;
+
+use compression::tree::TreeError;
+use franklin_crypto::bellman::SynthesisError;
+
+use crate::main_circuit::Capacity;
+
+///
+/// The proving API error.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// The batch doesn't fit into the circuit capacity.
+    CapacityExceeded {
+        capacity: Capacity,
+        transitions: usize,
+        compressed_data_size: usize,
+    },
+    /// The circuit synthesis or the proving system error.
+    Synthesis(SynthesisError),
+    /// The keys or proof reading/writing error.
+    Io(std::io::Error),
+    /// The keys or proof file is malformed.
+    InvalidFormat(String),
+    /// The keys or proof are for another circuit capacity.
+    CapacityMismatch {
+        expected: Capacity,
+        found: Capacity,
+    },
+    /// The keys or proof are for another verification key.
+    VerificationKeyMismatch,
+    /// The CRS has fewer powers than the circuit domain size.
+    CrsTooSmall {
+        required: usize,
+        available: usize,
+    },
+    /// The transitions can't be applied to the state tree.
+    Tree(TreeError),
+    /// The compressed data uses the encoding the circuit doesn't decode, e.g. the preimage and offset key(type 2).
+    UnsupportedEncoding {
+        transition: usize,
+        type_byte: u8,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CapacityExceeded { capacity, transitions, compressed_data_size } => write!(
+                f,
+                "{} transitions with {} bytes of compressed data don't fit into the capacity of {} transitions and {} bytes",
+                transitions, compressed_data_size, capacity.transitions, capacity.max_compressed_data_size
+            ),
+            Error::Synthesis(error) => write!(f, "synthesis error: {}", error),
+            Error::Io(error) => write!(f, "io error: {}", error),
+            Error::InvalidFormat(message) => write!(f, "invalid format: {}", message),
+            Error::CapacityMismatch { expected, found } => write!(
+                f,
+                "expected capacity of {} transitions and {} bytes, found {} transitions and {} bytes",
+                expected.transitions, expected.max_compressed_data_size, found.transitions, found.max_compressed_data_size
+            ),
+            Error::VerificationKeyMismatch => write!(f, "verification key hash mismatch"),
+            Error::CrsTooSmall { required, available } => write!(
+                f,
+                "the CRS has {} powers, but the domain size is {}",
+                available, required
+            ),
+            Error::Tree(error) => write!(f, "state tree error: {}", error),
+            Error::UnsupportedEncoding { transition, type_byte } => write!(
+                f,
+                "the transition #{} is compressed with the type {}, the circuit doesn't decode it",
+                transition, type_byte
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<SynthesisError> for Error {
+    fn from(error: SynthesisError) -> Self {
+        Error::Synthesis(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<TreeError> for Error {
+    fn from(error: TreeError) -> Self {
+        Error::Tree(error)
+    }
+}
@@ -0,0 +1,22 @@
+//!
+//! The circuit for proving compression correctness and the proving API over it.
+//!
+pub mod utils;
+pub mod commitment;
+pub mod dynamic_array;
+pub mod main_circuit;
+pub mod error;
+pub mod crs;
+pub mod proving;
+pub mod serialization;
+pub mod report;
+pub mod state_transition;
+#[cfg(test)]
+mod tests;
+
+pub use error::Error;
+pub use crs::CrsSource;
+pub use report::{gates_report, GatesReport};
+pub use main_circuit::{Capacity, CircuitOptions, CompressionCircuit, PublicInputMode};
+pub use proving::{prove, setup, verify, PackedPublicInputs, PublicInputs, SetupKeys};
+pub use state_transition::{state_transition_inputs, StateTransitionCircuit, StateTree};
@@ -1,91 +1,461 @@
+//!
+//! The command line interface for compression and proving.
+//!
+//! Exit codes:
+//! - 0: success;
+//! - 1: the proof is invalid;
+//! - 2: invalid arguments;
+//! - 3: the input can't be read or parsed, the output can't be written;
+//! - 4: the batch can't be proven(capacity, CRS or synthesis errors).
+//!
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
 use franklin_crypto::bellman::{
-    PrimeField, Field,
     compact_bn256::{Bn256, Fr, FrRepr},
-    kate_commitment::{Crs, CrsForMonomialForm},
-    plonk::{
-        // better_better_cs::cs::{Circuit, SelectorOptimizedWidth4MainGateWithDNext},
-        better_better_cs::cs::{Circuit, Width4MainGateWithDNext},
-        better_better_cs::gates::selector_optimized_with_d_next::*,
-
-        better_better_cs::{
-            cs::{PlonkCsWidth4WithNextStepParams, ProvingAssembly, SetupAssembly, TrivialAssembly},
-            setup::VerificationKey,
-            setup::SetupPrecomputations,
-            verifier::verify,
-        },
-        commitments::transcript::{keccak_transcript::RollingKeccakTranscript, Prng},
-    },
-    worker::Worker,
+    plonk::better_better_cs::{proof::Proof, setup::VerificationKey},
+    PrimeField, PrimeFieldRepr,
 };
-use compression::StorageTransition;
-use compression::sha3;
-use compression::sha3::Digest;
-
-pub(crate) mod utils;
-mod main_circuit;
-
-use crate::main_circuit::CompressionCircuit;
-
-fn main() {
-    let transitions = vec![StorageTransition {
-        address: [221, 31, 123, 46, 34, 67, 213, 90, 55, 0, 12, 54, 222, 56, 77, 0, 132, 12, 1, 5],
-        key: [31, 8, 32, 23, 2, 65, 222, 1, 34, 0, 6, 0, 234, 0, 0, 243, 0, 0, 22, 0, 0, 0, 234, 0, 122, 65, 33, 0, 4, 0, 4, 11],
-        value: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 222, 131, 5],
-        meta: None
-    }];
-
-    let data = StorageTransition::into_bytes(transitions.clone());
-    let compressed_data = StorageTransition::compress(transitions);
-    let mut circuit = CompressionCircuit::<Bn256>{
-        data: data.clone().into_iter().map(|byte| Some(byte)).collect(),
-        compressed_data: compressed_data.clone().into_iter().map(|byte| Some(byte)).collect(),
-        data_hash: sha3::Keccak256::digest(
-            data.as_slice()
-        ).as_slice().to_vec().into_iter().map(|byte| Some(byte)).collect::<Vec<Option<u8>>>(),
-        compressed_data_hash: sha3::Keccak256::digest(
-            compressed_data.as_slice()
-        ).as_slice().to_vec().into_iter().map(|byte| Some(byte)).collect::<Vec<Option<u8>>>(),
-        compressed_data_len: Some(Fr::from_repr(FrRepr([compressed_data.len() as u64, 0, 0, 0])).unwrap()),
-    };
+use compression::{StorageTransition, RECORD_SIZE};
 
-    let old_worker = Worker::new();
+use zk_compression::commitment::transitions_commitment;
+use zk_compression::serialization::{self, Artifact};
+use zk_compression::{
+    gates_report, prove, setup, verify, Capacity, CircuitOptions, CompressionCircuit, CrsSource, Error, PackedPublicInputs, PublicInputMode, PublicInputs,
+    SetupKeys,
+};
 
-    let mut assembly = TrivialAssembly::<
-        Bn256,
-        PlonkCsWidth4WithNextStepParams,
-        Width4MainGateWithDNext,
-    >::new();
+const EXIT_INVALID_PROOF: u8 = 1;
+const EXIT_INVALID_ARGUMENTS: u8 = 2;
+const EXIT_INVALID_INPUT: u8 = 3;
+const EXIT_PROVING_FAILED: u8 = 4;
 
-    circuit.synthesize(&mut assembly).expect("must work");
-    assert!(assembly.is_satisfied());
+#[derive(Parser)]
+#[command(name = "circuits-run", about = "Storage transitions compression with zero-knowledge proofs")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-    assembly.finalize();
+#[derive(Subcommand)]
+enum Command {
+    /// Compress the storage transitions.
+    Compress {
+        /// The transitions file.
+        input: PathBuf,
+        /// The compressed data file.
+        output: PathBuf,
+        /// The transitions file format, detected by the extension by default.
+        #[arg(long, value_enum)]
+        format: Option<TransitionsFormat>,
+        /// Print the statistics per type byte, field and contract address.
+        #[arg(long)]
+        stats: bool,
+    },
+    /// Uncompress the storage transitions.
+    Decompress {
+        /// The compressed data file.
+        input: PathBuf,
+        /// The transitions file.
+        output: PathBuf,
+        /// The transitions file format, detected by the extension by default.
+        #[arg(long, value_enum)]
+        format: Option<TransitionsFormat>,
+    },
+    /// Print the annotated listing of the compressed data.
+    Disassemble {
+        /// The compressed data file.
+        input: PathBuf,
+    },
+    /// Generate the setup keys and the verification key.
+    Setup {
+        /// The max number of transitions.
+        #[arg(long)]
+        capacity: usize,
+        /// The max compressed data size, the worst case for the capacity by default.
+        #[arg(long)]
+        max_compressed_data_size: Option<usize>,
+        /// The CRS file.
+        #[arg(long, required_unless_present = "insecure_test_crs")]
+        crs: Option<PathBuf>,
+        /// The CRS file format.
+        #[arg(long, value_enum, default_value = "ptau")]
+        crs_format: CrsFormat,
+        /// Use the CRS with the known toxic waste, proofs can be forged(needs the `insecure-test-crs` feature).
+        #[arg(long, conflicts_with = "crs")]
+        insecure_test_crs: bool,
+        /// What the circuit exposes as the public inputs.
+        #[arg(long, value_enum, default_value = "hashes")]
+        public_inputs: InputMode,
+        /// Accept only the encodings `compress` produces.
+        #[arg(long)]
+        canonical_encoding: bool,
+        /// The setup keys output file.
+        #[arg(long)]
+        keys: PathBuf,
+        /// The verification key output file.
+        #[arg(long)]
+        vk: PathBuf,
+    },
+    /// Print the gates count per circuit section.
+    Gates {
+        /// The max number of transitions.
+        #[arg(long)]
+        capacity: usize,
+        /// The max compressed data size, the worst case for the capacity by default.
+        #[arg(long)]
+        max_compressed_data_size: Option<usize>,
+        /// What the circuit exposes as the public inputs.
+        #[arg(long, value_enum, default_value = "hashes")]
+        public_inputs: InputMode,
+        /// Accept only the encodings `compress` produces.
+        #[arg(long)]
+        canonical_encoding: bool,
+    },
+    /// Prove the compression of the transitions. The batches with the keys compressed with the preimage
+    /// and offset(type 2) aren't supported by the circuit yet, they fail with the exit code 4.
+    Prove {
+        /// The setup keys file.
+        #[arg(long)]
+        keys: PathBuf,
+        /// The verification key file.
+        #[arg(long)]
+        vk: PathBuf,
+        /// The transitions file.
+        #[arg(long)]
+        transitions: PathBuf,
+        /// The transitions file format, detected by the extension by default.
+        #[arg(long, value_enum)]
+        format: Option<TransitionsFormat>,
+        /// The proof output file.
+        #[arg(long)]
+        proof: PathBuf,
+        /// The proof file format.
+        #[arg(long, value_enum, default_value = "binary")]
+        proof_format: ArtifactFormat,
+    },
+    /// Verify the proof against the public hashes or the commitment.
+    Verify {
+        /// The verification key file.
+        #[arg(long)]
+        vk: PathBuf,
+        /// The proof file(any format).
+        #[arg(long)]
+        proof: PathBuf,
+        /// The keccak256 of the uncompressed data.
+        #[arg(long, required_unless_present_any = ["transitions", "commitment"], requires = "compressed_data_hash")]
+        data_hash: Option<String>,
+        /// The keccak256 of the compressed data.
+        #[arg(long, requires = "data_hash")]
+        compressed_data_hash: Option<String>,
+        /// The public input commitment, for the verification key with the commitment public input.
+        #[arg(long, conflicts_with_all = ["data_hash", "transitions"])]
+        commitment: Option<String>,
+        /// The transitions file to compute the hashes or the commitment from.
+        #[arg(long, conflicts_with = "data_hash")]
+        transitions: Option<PathBuf>,
+        /// The transitions file format, detected by the extension by default.
+        #[arg(long, value_enum)]
+        format: Option<TransitionsFormat>,
+    },
+}
 
-    let domain_size = assembly.n().next_power_of_two();
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TransitionsFormat {
+    /// JSON array of `{"address", "key", "value", "meta"}` with 0x-prefixed hex values.
+    Json,
+    /// The same transitions objects, one per line.
+    JsonLines,
+    /// Raw 84-byte records of `StorageTransition::into_bytes`.
+    Raw,
+}
 
-    let crs_mons = Crs::<Bn256, CrsForMonomialForm>::crs_42(domain_size, &old_worker);
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CrsFormat {
+    /// snarkjs/Hermez powers of tau.
+    Ptau,
+    /// franklin `Crs::write`.
+    Franklin,
+}
 
-    let setup = assembly
-        .create_setup::<CompressionCircuit<Bn256>>(&old_worker)
-        .unwrap();
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputMode {
+    /// The keccak256 of the compressed and uncompressed data.
+    Hashes,
+    /// The single rescue commitment.
+    Commitment,
+}
 
+impl From<InputMode> for PublicInputMode {
+    fn from(mode: InputMode) -> Self {
+        match mode {
+            InputMode::Hashes => PublicInputMode::Hashes,
+            InputMode::Commitment => PublicInputMode::Commitment,
+        }
+    }
+}
 
-    let proof = assembly.clone()
-        .create_proof::<CompressionCircuit<Bn256>, RollingKeccakTranscript<Fr>>(
-            &old_worker,
-            &setup,
-            &crs_mons,
-            None,
-        )
-        .unwrap();
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ArtifactFormat {
+    Binary,
+    Hex,
+    Json,
+}
 
-    let vk = VerificationKey::from_setup(&setup, &old_worker, &crs_mons).unwrap();
+///
+/// The CLI failure with the exit code.
+///
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl Failure {
+    fn input(message: impl ToString) -> Self {
+        Self { code: EXIT_INVALID_INPUT, message: message.to_string() }
+    }
+
+    fn with_context(mut self, path: &Path) -> Self {
+        self.message = format!("{}: {}", path.display(), self.message);
+        self
+    }
+}
 
-    let valid =
-        verify::<Bn256, CompressionCircuit<Bn256>, RollingKeccakTranscript<Fr>>(&vk, &proof, None)
-            .unwrap();
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        let code = match error {
+            Error::Io(_) | Error::InvalidFormat(_) | Error::CapacityMismatch { .. } | Error::VerificationKeyMismatch => EXIT_INVALID_INPUT,
+            Error::CapacityExceeded { .. }
+            | Error::Synthesis(_)
+            | Error::CrsTooSmall { .. }
+            | Error::Tree(_)
+            | Error::UnsupportedEncoding { .. } => EXIT_PROVING_FAILED,
+        };
+        Self { code, message: error.to_string() }
+    }
+}
 
-    if valid {
-        println!("Proof is valid!😎");
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("Error: {}", failure.message);
+            ExitCode::from(failure.code)
+        },
     }
 }
+
+fn run(command: Command) -> Result<(), Failure> {
+    match command {
+        Command::Compress { input, output, format, stats } => {
+            let transitions = read_transitions(&input, format)?;
+            let (compressed, compression_stats) = StorageTransition::compress_with_stats(transitions.clone());
+            write_file(&output, &compressed)?;
+            println!(
+                "Compressed {} transitions: {} -> {} bytes",
+                transitions.len(),
+                transitions.len() * RECORD_SIZE,
+                compressed.len()
+            );
+            if stats {
+                println!("{}", compression_stats);
+            }
+        },
+        Command::Decompress { input, output, format } => {
+            let compressed = read_file(&input)?;
+            let transitions = StorageTransition::try_uncompress(&compressed)
+                .map_err(|error| Failure::input(error).with_context(&input))?;
+            write_transitions(&output, format, &transitions)?;
+            println!("Decompressed {} transitions", transitions.len());
+        },
+        Command::Disassemble { input } => {
+            let disassembly = compression::disassemble(&read_file(&input)?);
+            println!("{}", disassembly);
+            if let Some(error) = disassembly.error {
+                return Err(Failure::input(error).with_context(&input));
+            }
+        },
+        Command::Setup { capacity, max_compressed_data_size, crs, crs_format, insecure_test_crs, public_inputs, canonical_encoding, keys, vk } => {
+            let mut capacity = Capacity::new(capacity);
+            if let Some(max_compressed_data_size) = max_compressed_data_size {
+                capacity.max_compressed_data_size = max_compressed_data_size;
+            }
+
+            let crs = match (crs, crs_format) {
+                _ if insecure_test_crs => insecure_test_crs_source()?,
+                (Some(path), CrsFormat::Ptau) => CrsSource::Ptau(path),
+                (Some(path), CrsFormat::Franklin) => CrsSource::Franklin(path),
+                (None, _) => unreachable!("the CRS is required by the arguments"),
+            };
+
+            let options = CircuitOptions {
+                public_input_mode: PublicInputMode::from(public_inputs),
+                canonical_encoding,
+            };
+            let (setup_keys, verification_key) = setup(capacity, options, &crs)?;
+            let vk_hash = serialization::vk_hash(&verification_key)?;
+
+            let mut bytes = Vec::new();
+            serialization::write(&setup_keys, capacity, options, vk_hash, &mut bytes)?;
+            write_file(&keys, &bytes)?;
+
+            let mut bytes = Vec::new();
+            serialization::write_verification_key(&verification_key, capacity, options, &mut bytes)?;
+            write_file(&vk, &bytes)?;
+
+            println!("Verification key hash: 0x{}", hex::encode(vk_hash));
+        },
+        Command::Gates { capacity, max_compressed_data_size, public_inputs, canonical_encoding } => {
+            let mut capacity = Capacity::new(capacity);
+            if let Some(max_compressed_data_size) = max_compressed_data_size {
+                capacity.max_compressed_data_size = max_compressed_data_size;
+            }
+            let options = CircuitOptions {
+                public_input_mode: PublicInputMode::from(public_inputs),
+                canonical_encoding,
+            };
+
+            println!("{}", gates_report(capacity, options)?);
+        },
+        Command::Prove { keys, vk, transitions, format, proof, proof_format } => {
+            let (_, header) = read_artifact::<VerificationKey<Bn256, CompressionCircuit<Bn256>>>(&vk, None, None)?;
+            let (setup_keys, _) = read_artifact::<SetupKeys>(&keys, Some(header.capacity), Some(header.vk_hash))?;
+            let transitions = read_transitions(&transitions, format)?;
+
+            let result = prove(&setup_keys, &transitions)?;
+
+            let bytes = match proof_format {
+                ArtifactFormat::Binary => {
+                    let mut bytes = Vec::new();
+                    serialization::write(&result, header.capacity, header.options, header.vk_hash, &mut bytes)?;
+                    bytes
+                },
+                ArtifactFormat::Hex => serialization::to_hex(&result, header.capacity, header.options, header.vk_hash)?.into_bytes(),
+                ArtifactFormat::Json => serialization::to_json(&result, header.capacity, header.options, header.vk_hash)?.into_bytes(),
+            };
+            write_file(&proof, &bytes)?;
+
+            let public_inputs = PublicInputs::from_transitions(&transitions);
+            println!("Data hash: 0x{}", hex::encode(public_inputs.data_hash));
+            println!("Compressed data hash: 0x{}", hex::encode(public_inputs.compressed_data_hash));
+            if header.options.public_input_mode == PublicInputMode::Commitment {
+                println!("Commitment: {}", fr_to_hex(&transitions_commitment(header.capacity, &transitions)));
+            }
+        },
+        Command::Verify { vk, proof, data_hash, compressed_data_hash, commitment, transitions, format } => {
+            let (verification_key, header) = read_artifact::<VerificationKey<Bn256, CompressionCircuit<Bn256>>>(&vk, None, None)?;
+            let (proof, _) = read_artifact::<Proof<Bn256, CompressionCircuit<Bn256>>>(&proof, Some(header.capacity), Some(header.vk_hash))?;
+
+            let public_inputs = match (header.options.public_input_mode, transitions, commitment, data_hash, compressed_data_hash) {
+                (PublicInputMode::Hashes, Some(transitions), _, _, _) => PublicInputs::from_transitions(&read_transitions(&transitions, format)?).pack(),
+                (PublicInputMode::Hashes, None, None, Some(data_hash), Some(compressed_data_hash)) => PublicInputs {
+                    data_hash: parse_hex(&data_hash)?,
+                    compressed_data_hash: parse_hex(&compressed_data_hash)?,
+                }
+                .pack(),
+                (PublicInputMode::Commitment, Some(transitions), _, _, _) => {
+                    PackedPublicInputs::commitment(transitions_commitment(header.capacity, &read_transitions(&transitions, format)?))
+                },
+                (PublicInputMode::Commitment, None, Some(commitment), _, _) => PackedPublicInputs::commitment(parse_fr(&commitment)?),
+                (public_input_mode, ..) => {
+                    return Err(Failure {
+                        code: EXIT_INVALID_ARGUMENTS,
+                        message: format!("the verification key expects the {:?} public inputs", public_input_mode),
+                    })
+                },
+            };
+
+            if !verify(&verification_key, &proof, &public_inputs)? {
+                return Err(Failure { code: EXIT_INVALID_PROOF, message: "the proof is invalid".to_owned() });
+            }
+            println!("Proof is valid!😎");
+        },
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "insecure-test-crs")]
+fn insecure_test_crs_source() -> Result<CrsSource, Failure> {
+    Ok(CrsSource::InsecureTest)
+}
+
+#[cfg(not(feature = "insecure-test-crs"))]
+fn insecure_test_crs_source() -> Result<CrsSource, Failure> {
+    Err(Failure {
+        code: EXIT_INVALID_ARGUMENTS,
+        message: "built without the `insecure-test-crs` feature".to_owned(),
+    })
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Failure> {
+    fs::read(path).map_err(|error| Failure::input(format!("{}: {}", path.display(), error)))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Failure> {
+    fs::write(path, data).map_err(|error| Failure::input(format!("{}: {}", path.display(), error)))
+}
+
+fn read_artifact<A: Artifact>(path: &Path, capacity: Option<Capacity>, vk_hash: Option<[u8; 32]>) -> Result<(A, serialization::Header), Failure> {
+    serialization::decode(&read_file(path)?, capacity, vk_hash)
+        .map_err(|error| Failure::from(error).with_context(path))
+}
+
+fn transitions_format(path: &Path, format: Option<TransitionsFormat>) -> TransitionsFormat {
+    format.unwrap_or_else(|| match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => TransitionsFormat::Json,
+        Some("jsonl") => TransitionsFormat::JsonLines,
+        _ => TransitionsFormat::Raw,
+    })
+}
+
+fn read_transitions(path: &Path, format: Option<TransitionsFormat>) -> Result<Vec<StorageTransition>, Failure> {
+    let data = read_file(path)?;
+    match transitions_format(path, format) {
+        TransitionsFormat::Raw => StorageTransition::from_bytes(&data)
+            .map_err(|error| Failure::input(format!("{}: {}", path.display(), error))),
+        TransitionsFormat::Json => serde_json::from_slice(&data)
+            .map_err(|error| Failure::input(format!("{}: {}", path.display(), error))),
+        TransitionsFormat::JsonLines => {
+            let data = String::from_utf8(data).map_err(|error| Failure::input(format!("{}: {}", path.display(), error)))?;
+            StorageTransition::from_json_lines(&data).map_err(|error| Failure::input(format!("{}: {}", path.display(), error)))
+        },
+    }
+}
+
+fn write_transitions(path: &Path, format: Option<TransitionsFormat>, transitions: &[StorageTransition]) -> Result<(), Failure> {
+    let data = match transitions_format(path, format) {
+        TransitionsFormat::Raw => StorageTransition::into_bytes(transitions.to_vec()),
+        TransitionsFormat::Json => serde_json::to_vec_pretty(transitions).map_err(Failure::input)?,
+        TransitionsFormat::JsonLines => StorageTransition::to_json_lines(transitions).into_bytes(),
+    };
+
+    write_file(path, &data)
+}
+
+fn parse_hex<const N: usize>(value: &str) -> Result<[u8; N], Failure> {
+    let mut result = [0u8; N];
+    hex::decode_to_slice(value.trim_start_matches("0x"), &mut result)
+        .map_err(|error| Failure::input(format!("`{}`: {}", value, error)))?;
+
+    Ok(result)
+}
+
+fn parse_fr(value: &str) -> Result<Fr, Failure> {
+    let bytes = parse_hex::<32>(value)?;
+    let mut repr = FrRepr::default();
+    repr.read_be(bytes.as_slice()).map_err(Failure::input)?;
+
+    Fr::from_repr(repr).map_err(|error| Failure::input(format!("`{}`: {}", value, error)))
+}
+
+fn fr_to_hex(value: &Fr) -> String {
+    let mut bytes = Vec::with_capacity(32);
+    value.into_repr().write_be(&mut bytes).expect("writing to the vector never fails");
+
+    format!("0x{}", hex::encode(bytes))
+}
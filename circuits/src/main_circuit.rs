@@ -25,192 +25,637 @@ pub use franklin_crypto::{
         custom_rescue_gate::Rescue5CustomGate,
     },
 };
+use franklin_crypto::bellman::plonk::better_better_cs::cs::{PlonkCsWidth4WithNextStepParams, SetupAssembly};
+use franklin_crypto::bellman::plonk::better_better_cs::lookup_tables::{TwoKeysOneValueBinopTable, XorBinop};
 use franklin_crypto::plonk::circuit::Assignment;
+use franklin_crypto::plonk::circuit::linear_combination::LinearCombination;
 
-pub const MAX_COMPRESSED_DATA_SIZE: usize = 132;// 33 * 3 + 33
-pub const MAX_UNCOMPRESSED_DATA_SIZE: usize = 129;// 32 * 3 + 33
-pub const MAX_WORDS: usize = 3;
+use serde::{Deserialize, Serialize};
+
+use compression::disassemble::Encoding;
+use compression::{StorageTransition, MAX_COMPRESSED_RECORD_SIZE, RECORD_SIZE};
+
+use crate::commitment::circuit_public_input_commitment;
+use crate::dynamic_array::DynamicByteArray;
+use crate::error::Error;
+use crate::proving::PublicInputs;
+use crate::report::{
+    GatesReport, ACTIVE_FLAGS, ADDRESS_DECODING, KEY_VALUE_DECODING, MEMORY_CONSISTENCY, PUBLIC_INPUTS, RANGE_CHECKS,
+    WORD_EXTRACTION,
+};
+use crate::utils::{boolean_to_num, fr_from_usize, fr_to_usize};
+
+/// The number of bytes packed into one public input, 16 bytes fit the field.
+pub const PACKED_INPUT_BYTES: usize = 16;
+
+const BYTE_PAIRS_TABLE_NAME: &str = "Byte pairs xor table";
+
+///
+/// How the bytes are range checked.
+///
+/// The width 3 range table checks only the first column(its rows are `(x, 0, 0)`), checking three
+/// bytes by one lookup would need the table of 2^24 rows. The two keys xor table of 2^16 rows
+/// checks two bytes by one lookup. Splitting the bytes into nibbles for the 2^12-row table of three
+/// nibbles doesn't help: every byte still needs the gate joining its two nibbles.
+///
+/// The domain must fit both the gates and the lookups with the table entries, so `Pairs` pays off
+/// as soon as the circuit has more rows than its table, see `cheapest`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRangeCheck {
+    /// One byte per lookup in the 8-bit range table of 2^8 rows.
+    Single,
+    /// Two bytes per lookup in the 8-bit xor table of 2^16 rows, the third column is their xor.
+    Pairs,
+}
+
+impl ByteRangeCheck {
+    ///
+    /// The range check with the fewer rows for the circuit `synthesize` makes with it.
+    /// The circuit is synthesized without witness, so the setup and the proving choose the same check.
+    ///
+    pub fn cheapest<E, F>(synthesize: F) -> Result<Self, SynthesisError>
+    where
+        E: Engine,
+        F: Fn(&mut SetupAssembly<E, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>, ByteRangeCheck) -> Result<(), SynthesisError>,
+    {
+        let single = ByteRangeCheck::Single.rows::<E, _>(&synthesize)?;
+        // The pairs table alone has more rows.
+        if single <= ByteRangeCheck::Pairs.table_rows() {
+            return Ok(ByteRangeCheck::Single);
+        }
+        let pairs = ByteRangeCheck::Pairs.rows::<E, _>(&synthesize)?;
+
+        Ok(if pairs < single { ByteRangeCheck::Pairs } else { ByteRangeCheck::Single })
+    }
+
+    ///
+    /// The rows of the circuit `synthesize` makes with the range check: the gates or the lookups with
+    /// the table entries, whichever is more.
+    ///
+    pub fn rows<E, F>(self, synthesize: F) -> Result<usize, SynthesisError>
+    where
+        E: Engine,
+        F: Fn(&mut SetupAssembly<E, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>, ByteRangeCheck) -> Result<(), SynthesisError>,
+    {
+        let mut cs = SetupAssembly::<E, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>::new();
+        synthesize(&mut cs, self)?;
+
+        Ok(cs.n().max(cs.num_table_lookups + self.table_rows()))
+    }
+
+    pub const fn table_rows(self) -> usize {
+        match self {
+            ByteRangeCheck::Single => 1 << 8,
+            ByteRangeCheck::Pairs => 1 << 16,
+        }
+    }
+
+    pub const fn bytes_per_lookup(self) -> usize {
+        match self {
+            ByteRangeCheck::Single => 1,
+            ByteRangeCheck::Pairs => 2,
+        }
+    }
+
+    pub(crate) fn table<E: Engine>(self) -> Result<LookupTableApplication<E>, SynthesisError> {
+        let columns = vec![
+            PolyIdentifier::VariablesPolynomial(0),
+            PolyIdentifier::VariablesPolynomial(1),
+            PolyIdentifier::VariablesPolynomial(2),
+        ];
+        match self {
+            ByteRangeCheck::Single => LookupTableApplication::new_range_table_of_width_3(8, columns),
+            ByteRangeCheck::Pairs => Ok(LookupTableApplication::new(
+                BYTE_PAIRS_TABLE_NAME,
+                TwoKeysOneValueBinopTable::<E, XorBinop>::new(8, BYTE_PAIRS_TABLE_NAME),
+                columns,
+                None,
+                true,
+            )),
+        }
+    }
+}
+
+///
+/// The circuit capacity, every capacity is the separate circuit with its own setup keys.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capacity {
+    /// The max number of storage transitions.
+    pub transitions: usize,
+    /// The max size of the compressed data.
+    pub max_compressed_data_size: usize,
+}
+
+impl Capacity {
+    ///
+    /// Capacity for the transitions number with the worst case compressed data size.
+    ///
+    pub const fn new(transitions: usize) -> Self {
+        Self {
+            transitions,
+            max_compressed_data_size: transitions * MAX_COMPRESSED_RECORD_SIZE,
+        }
+    }
+
+    ///
+    /// The number of the words(address, key, value for every transition).
+    ///
+    pub const fn words(&self) -> usize {
+        self.transitions * 3
+    }
+
+    ///
+    /// The size of the uncompressed data.
+    ///
+    pub const fn max_uncompressed_data_size(&self) -> usize {
+        self.transitions * RECORD_SIZE
+    }
+}
+
+///
+/// What the circuit exposes as the public inputs.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicInputMode {
+    /// The keccak256 of the compressed data, then of the uncompressed data, packed by `PACKED_INPUT_BYTES`.
+    #[default]
+    Hashes,
+    /// The single `rescue(keccak(compressed_data), rescue(data))`, see `commitment::public_input_commitment`.
+    /// The keccak half is an unconstrained witness, it doesn't bind the compressed data.
+    Commitment,
+}
+
+impl PublicInputMode {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            PublicInputMode::Hashes => 0,
+            PublicInputMode::Commitment => 1,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(PublicInputMode::Hashes),
+            1 => Ok(PublicInputMode::Commitment),
+            _ => Err(Error::InvalidFormat(format!("unknown public input mode {}", byte))),
+        }
+    }
+}
+
+///
+/// The circuit options, every combination is a separate circuit with its own setup keys.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CircuitOptions {
+    pub public_input_mode: PublicInputMode,
+    /// Accept only the encodings `compress` produces, the same as `StorageTransition::uncompress_canonical`.
+    pub canonical_encoding: bool,
+}
 
 ///
 /// The main circuit structure.
 ///
 pub struct CompressionCircuit<E: Engine> {
+    pub capacity: Capacity,
     pub data: Vec<Option<u8>>,
     pub compressed_data: Vec<Option<u8>>,
     pub data_hash: Vec<Option<u8>>,
     pub compressed_data_hash: Vec<Option<u8>>,
     pub compressed_data_len: Option<E::Fr>,
+    /// The number of the real transitions, the rest of the slots are inactive.
+    pub transitions_count: Option<E::Fr>,
+    pub options: CircuitOptions,
+}
+
+impl<E: Engine> CompressionCircuit<E> {
+    ///
+    /// The circuit with witness for the storage transitions batch.
+    /// The keys compressed with the preimage and offset(the transitions with meta) aren't decoded
+    /// by the circuit, such batches are rejected here instead of making the proof that never verifies.
+    ///
+    pub fn from_transitions(capacity: Capacity, transitions: &[StorageTransition]) -> Result<Self, Error> {
+        let data = StorageTransition::into_bytes(transitions.to_vec());
+        let compressed_data = StorageTransition::compress(transitions.to_vec());
+        let disassembly = compression::disassemble(&compressed_data);
+        if let Some(instruction) = disassembly
+            .instructions
+            .iter()
+            .find(|instruction| matches!(instruction.encoding, Encoding::PreimageOffset { .. }))
+        {
+            return Err(Error::UnsupportedEncoding { transition: instruction.record, type_byte: instruction.type_byte });
+        }
+        if transitions.len() > capacity.transitions || compressed_data.len() > capacity.max_compressed_data_size {
+            return Err(Error::CapacityExceeded {
+                capacity,
+                transitions: transitions.len(),
+                compressed_data_size: compressed_data.len(),
+            });
+        }
+
+        let public_inputs = PublicInputs::new(&data, &compressed_data);
+        Ok(Self {
+            capacity,
+            data: data.into_iter().map(Some).collect(),
+            compressed_data_len: Some(fr_from_usize(compressed_data.len())),
+            compressed_data: compressed_data.into_iter().map(Some).collect(),
+            data_hash: public_inputs.data_hash.into_iter().map(Some).collect(),
+            compressed_data_hash: public_inputs.compressed_data_hash.into_iter().map(Some).collect(),
+            transitions_count: Some(fr_from_usize(transitions.len())),
+            options: CircuitOptions::default(),
+        })
+    }
+
+    ///
+    /// The circuit without witness, enough for the setup generation.
+    ///
+    pub fn without_witness(capacity: Capacity) -> Self {
+        Self {
+            capacity,
+            data: vec![None; capacity.max_uncompressed_data_size()],
+            compressed_data: vec![None; capacity.max_compressed_data_size],
+            data_hash: vec![None; 32],
+            compressed_data_hash: vec![None; 32],
+            compressed_data_len: None,
+            transitions_count: None,
+            options: CircuitOptions::default(),
+        }
+    }
+
+    ///
+    /// Set the circuit options.
+    ///
+    pub fn with_options(mut self, options: CircuitOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
 
 impl<E: Engine> Circuit<E> for CompressionCircuit<E> {
     type MainGate = Width4MainGateWithDNext;
 
     fn synthesize<CS: ConstraintSystem<E>>(&self, cs: &mut CS) -> Result<(), SynthesisError> {
-        let columns = vec![
-            PolyIdentifier::VariablesPolynomial(0),
-            PolyIdentifier::VariablesPolynomial(1),
-            PolyIdentifier::VariablesPolynomial(2),
-        ];
-        let range_table = LookupTableApplication::new_range_table_of_width_3(8, columns.clone())?;
+        self.synthesize_with_report(cs, &mut GatesReport::default())
+    }
+}
+
+impl<E: Engine> CompressionCircuit<E> {
+    ///
+    /// Synthesize the circuit recording the gates count per section into the report.
+    ///
+    pub fn synthesize_with_report<CS: ConstraintSystem<E>>(&self, cs: &mut CS, report: &mut GatesReport) -> Result<(), SynthesisError> {
+        let range_check = self.range_check()?;
+        self.synthesize_with_range_check(cs, range_check, report)
+    }
+
+    ///
+    /// The range check with the fewer rows for this circuit, see `ByteRangeCheck::cheapest`.
+    ///
+    pub fn range_check(&self) -> Result<ByteRangeCheck, SynthesisError> {
+        ByteRangeCheck::cheapest::<E, _>(|cs, range_check| self.synthesize_with_range_check(cs, range_check, &mut GatesReport::default()))
+    }
+
+    pub(crate) fn synthesize_with_range_check<CS: ConstraintSystem<E>>(
+        &self,
+        cs: &mut CS,
+        range_check: ByteRangeCheck,
+        report: &mut GatesReport,
+    ) -> Result<(), SynthesisError> {
+        report.start(cs);
+        let decoded = self.decode(cs, range_check, report)?;
+
+        match self.options.public_input_mode {
+            PublicInputMode::Hashes => {
+                let data_hash = allocate_and_prove_bytes(&self.data_hash, 32, cs, decoded.range_check, decoded.range_table_name.as_str())?;
+                report.record(cs, RANGE_CHECKS);
+                pack_bytes_as_inputs(cs, &decoded.compressed_data_hash)?;
+                pack_bytes_as_inputs(cs, &data_hash)?;
+            },
+            PublicInputMode::Commitment => {
+                let commitment = circuit_public_input_commitment(cs, &decoded.data, &decoded.compressed_data_hash)?;
+                let input = AllocatedNum::alloc_input(cs, || commitment.get_value().grab())?;
+                commitment.enforce_equal(cs, &Num::Variable(input))?;
+            },
+        }
+        report.record(cs, PUBLIC_INPUTS);
+
+        Ok(())
+    }
+
+    ///
+    /// Decode the compressed data into the uncompressed data, without the public inputs.
+    ///
+    pub(crate) fn decode<CS: ConstraintSystem<E>>(
+        &self,
+        cs: &mut CS,
+        range_check: ByteRangeCheck,
+        report: &mut GatesReport,
+    ) -> Result<DecodedData<E>, SynthesisError> {
+        if self.data.len() > self.capacity.max_uncompressed_data_size()
+            || self.compressed_data.len() > self.capacity.max_compressed_data_size {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let range_table = range_check.table()?;
         let range_table_name = range_table.functional_name();
         cs.add_table(range_table)?;
 
-        let compressed_data_hash_bytes = allocate_and_prove_bytes(&self.compressed_data_hash, 32, cs, range_table_name.as_str(), true)?;
-        let data_hash = allocate_and_prove_bytes(&self.data_hash, 32, cs, range_table_name.as_str(), true)?;
-
-        let compressed_data_bytes = allocate_and_prove_bytes(&self.compressed_data, MAX_COMPRESSED_DATA_SIZE, cs, range_table_name.as_str(), false)?;
-        let data_bytes = allocate_and_prove_bytes(&self.data, MAX_UNCOMPRESSED_DATA_SIZE, cs, range_table_name.as_str(), false)?;
+        let compressed_data_hash_bytes = allocate_and_prove_bytes(&self.compressed_data_hash, 32, cs, range_check, range_table_name.as_str())?;
+        let mut compressed_data_bytes = DynamicByteArray::new(allocate_and_prove_bytes(&self.compressed_data, self.capacity.max_compressed_data_size, cs, range_check, range_table_name.as_str())?);
+        let data_bytes = allocate_and_prove_bytes(&self.data, self.capacity.max_uncompressed_data_size(), cs, range_check, range_table_name.as_str())?;
+        report.record(cs, RANGE_CHECKS);
 
         // TODO: prove hashes correctness
 
-        let _compressed_data_len = Num::alloc(
+        let compressed_data_len = Num::alloc(
             cs,
             self.compressed_data_len
         )?;
 
-        let mut ptr = Num::alloc(
+        let transitions_count = Num::alloc(
             cs,
-            Some(E::Fr::from_str("0").unwrap())
+            self.transitions_count
         )?;
 
-        let zero = Num::alloc(
-            cs,
-            Some(E::Fr::from_str("0").unwrap())
-        )?;
+        // The constants are not witnesses, otherwise the prover could choose them.
+        let mut ptr = Num::Constant(E::Fr::zero());
+        let one = Num::Constant(E::Fr::one());
 
-        let one = Num::alloc(
-            cs,
-            Some(E::Fr::from_str("1").unwrap())
-        )?;
+        // The active slots are the prefix of the `transitions_count` length.
+        let mut active_flags: Vec<Boolean> = Vec::with_capacity(self.capacity.transitions);
+        let mut active_count = Num::Constant(E::Fr::zero());
+        for transition in 0..self.capacity.transitions {
+            let active = Boolean::alloc(cs, self.transitions_count.map(|count| transition < fr_to_usize(count)))?;
+            if let Some(previous) = active_flags.last() {
+                let gap = Boolean::and(cs, &active, &previous.not())?;
+                Boolean::enforce_equal(cs, &gap, &Boolean::constant(false))?;
+            }
+            active_count = active_count.add(cs, &Num::from_boolean_is(active))?;
 
-        for word in 0..MAX_WORDS {
-            let compressed_word = get_word_from_bytes(cs, &compressed_data_bytes, &ptr)?;
-            assert_eq!(compressed_word.len(), 33);
+            // The uncompressed data of the inactive slot is zeroes.
+            let inactive = boolean_to_num(cs, &active.not())?;
+            for byte in &data_bytes[transition * RECORD_SIZE..(transition + 1) * RECORD_SIZE] {
+                let masked = byte.inner.mul(cs, &inactive)?;
+                masked.enforce_equal(cs, &Num::Constant(E::Fr::zero()))?;
+            }
+
+            active_flags.push(active);
+        }
+        active_count.enforce_equal(cs, &transitions_count)?;
+        report.record(cs, ACTIVE_FLAGS);
+
+        for word in 0..self.capacity.words() {
+            let active = active_flags[word / 3];
             let (uncompressed_pos, size) = crate::utils::get_word_position_in_data(word);
-            let mut ok = Boolean::alloc(cs, Some(false))?;
             if size == 20 {
-                let mut is1 = Num::equals(cs,&compressed_word[0].inner, &one)?;
+                let compressed_word = compressed_data_bytes.read_word(cs, &ptr, 21)?;
+                report.record(cs, WORD_EXTRACTION);
+
+                let mut ok = Num::equals(cs, &compressed_word[0].inner, &one)?;
                 for i in 0..20 {
                     let eq = Num::equals(cs, &data_bytes[uncompressed_pos + i].inner, &compressed_word[i + 1].inner)?;
-                    is1 = Boolean::and(cs, &is1, &eq)?;
+                    ok = Boolean::and(cs, &ok, &eq)?;
                 }
-                ok = is1;
-                let _21 = Num::alloc(
-                    cs,
-                    Some(E::Fr::from_str("21").unwrap())
-                )?;
-                ptr = ptr.add(cs, &_21)?;
+                // The inactive slot isn't decoded.
+                ok = Boolean::or(cs, &ok, &active.not())?;
+                Boolean::enforce_equal(cs, &ok, &Boolean::constant(true))?;
+
+                let word_size = Num::from_boolean_is(active).mul(cs, &Num::Constant(fr_from_usize(21)))?;
+                ptr = ptr.add(cs, &word_size)?;
+                report.record(cs, ADDRESS_DECODING);
             } else {
-                let mut is0 = Num::equals(cs,&compressed_word[0].inner, &zero)?;
-                for i in 0..32 {
-                    let eq = Num::equals(cs, &data_bytes[uncompressed_pos + i].inner, &compressed_word[i + 1].inner)?;
-                    is0 = Boolean::and(cs, &is0, &eq)?;
-                }
-                ok = Boolean::or(cs, &ok, &is0)?;
-
-                for i in 11..=42 {
-                    let i_num = Num::alloc(
-                        cs,
-                        Some(E::Fr::from_str(&format!("{}", i)).unwrap())
-                    )?;
-                    let mut is = Num::equals(cs,&compressed_word[0].inner, &i_num)?;
-                    for index in 0..32 {
-                        let eq;
-                        if index < i - 10 {
-                            eq = Num::equals(cs, &data_bytes[uncompressed_pos + index].inner, &zero)?;
-                        } else {
-                            eq = Num::equals(cs, &data_bytes[uncompressed_pos + index].inner, &compressed_word[index - (i - 10) + 1].inner)?;
-                        }
-                        is = Boolean::and(cs, &is,&eq)?;
-                    }
-                    ok = Boolean::or(cs, &ok, &is)?;
-                }
-                let _33 = Num::alloc(
+                let data_word = &data_bytes[uncompressed_pos..uncompressed_pos + 32];
+                let zeroes = decode_word(
                     cs,
-                    Some(E::Fr::from_str("33").unwrap())
+                    &mut compressed_data_bytes,
+                    &ptr,
+                    active,
+                    data_word,
+                    self.options.canonical_encoding,
+                    range_check,
+                    range_table_name.as_str(),
                 )?;
-                ptr = ptr.add(cs, &_33)?;
+
+                // The encoded word size is the type byte and the suffix after the leading zeroes.
+                let word_size = Num::Constant(fr_from_usize(33))
+                    .sub(cs, &zeroes)?
+                    .mul(cs, &Num::from_boolean_is(active))?;
+                ptr = ptr.add(cs, &word_size)?;
+                report.record(cs, KEY_VALUE_DECODING);
             }
-            // TODO: Add 2 byte type
-            let true_bool = Boolean::alloc(cs, Some(true))?;
-            Boolean::enforce_equal(cs, &ok, &true_bool)?;
         }
-        Ok(())
+        ptr.enforce_equal(cs, &compressed_data_len)?;
+        report.record(cs, WORD_EXTRACTION);
+        compressed_data_bytes.enforce_consistency(cs)?;
+        report.record(cs, MEMORY_CONSISTENCY);
+
+        Ok(DecodedData {
+            data: data_bytes,
+            compressed_data_hash: compressed_data_hash_bytes,
+            active_flags,
+            range_check,
+            range_table_name,
+        })
     }
 }
 
 ///
-/// Allocate byte array and prove tha values of bytes.
+/// The decoded data for the circuits built on top of the decoding.
 ///
-fn allocate_and_prove_bytes<E: Engine, CS: ConstraintSystem<E>>(bytes: &Vec<Option<u8>>, len: usize, cs: &mut CS, range_table_name: &str, alloc_as_inputs: bool) -> Result<Vec<Byte<E>>, SynthesisError> {
-    let mut result = Vec::with_capacity(bytes.len());
+pub(crate) struct DecodedData<E: Engine> {
+    /// The uncompressed data, the inactive slots are zeroes.
+    pub data: Vec<Byte<E>>,
+    pub compressed_data_hash: Vec<Byte<E>>,
+    /// The active transition slots flags.
+    pub active_flags: Vec<Boolean>,
+    pub range_check: ByteRangeCheck,
+    pub range_table_name: String,
+}
+
+///
+/// Allocate byte array and prove tha values of bytes, `range_check.bytes_per_lookup()` bytes per lookup.
+///
+pub(crate) fn allocate_and_prove_bytes<E: Engine, CS: ConstraintSystem<E>>(
+    bytes: &Vec<Option<u8>>,
+    len: usize,
+    cs: &mut CS,
+    range_check: ByteRangeCheck,
+    range_table_name: &str,
+) -> Result<Vec<Byte<E>>, SynthesisError> {
+    let mut result = Vec::with_capacity(len);
 
     for i in 0..len {
-        let byte = bytes.get(i).unwrap_or(&Some(0));
-        let inner = if alloc_as_inputs {
-            Num::Variable(AllocatedNum::alloc_input(cs, || Ok(E::Fr::from_str(&format!("{}", byte.unwrap())).unwrap()))?)
-        } else {
-            Num::alloc(
-                cs,
-                Some(E::Fr::from_str(&format!("{}", byte.unwrap())).unwrap())
-            )?
-        };
+        // Witness closures are evaluated only by the proving assemblies.
+        let witness = bytes
+            .get(i)
+            .copied()
+            .unwrap_or(Some(0))
+            .map(|byte| fr_from_usize::<E::Fr>(byte as usize));
+        result.push(Byte { inner: Num::alloc(cs, witness)? });
+    }
 
-        let table = cs.get_table(range_table_name)?;
-        let num_keys_and_values = table.width();
+    let nums: Vec<Num<E>> = result.iter().map(|byte| byte.inner.clone()).collect();
+    enforce_bytes_range(cs, &nums, range_check, range_table_name)?;
 
-        let var_zero = cs.get_explicit_zero()?;
-        let dummy = CS::get_dummy_variable();
+    Ok(result)
+}
 
-        let inner_var = inner.get_variable().get_variable();
-        let vars = [inner_var, var_zero.clone(), var_zero.clone(), dummy];
+///
+/// Prove that the allocated numbers are bytes, `range_check.bytes_per_lookup()` numbers per lookup.
+///
+pub(crate) fn enforce_bytes_range<E: Engine, CS: ConstraintSystem<E>>(
+    cs: &mut CS,
+    nums: &[Num<E>],
+    range_check: ByteRangeCheck,
+    range_table_name: &str,
+) -> Result<(), SynthesisError> {
+    let table = cs.get_table(range_table_name)?;
+    let num_keys_and_values = table.width();
+    let var_zero = cs.get_explicit_zero()?;
+    let dummy = CS::get_dummy_variable();
+
+    for chunk in nums.chunks(range_check.bytes_per_lookup()) {
+        let first = chunk[0].get_variable();
+        let vars = match (range_check, chunk.get(1)) {
+            (ByteRangeCheck::Single, _) => [first.get_variable(), var_zero, var_zero, dummy],
+            // The odd byte is paired with zero, their xor is the byte itself.
+            (ByteRangeCheck::Pairs, None) => [first.get_variable(), var_zero, first.get_variable(), dummy],
+            (ByteRangeCheck::Pairs, Some(second)) => {
+                let second = second.get_variable();
+                let xor = AllocatedNum::alloc(cs, || {
+                    let first = fr_to_usize(*first.get_value().get()?);
+                    let second = fr_to_usize(*second.get_value().get()?);
+                    Ok(fr_from_usize(first ^ second))
+                })?;
+                [first.get_variable(), second.get_variable(), xor.get_variable(), dummy]
+            },
+        };
 
         cs.begin_gates_batch_for_step()?;
+        cs.allocate_variables_without_gate(&vars, &[])?;
+        cs.apply_single_lookup_gate(&vars[..num_keys_and_values], table.clone())?;
+        cs.end_gates_batch_for_step()?;
+    }
 
-        cs.allocate_variables_without_gate(
-            &vars,
-            &[]
-        )?;
+    Ok(())
+}
 
-        cs.apply_single_lookup_gate(&vars[..num_keys_and_values], table)?;
-        cs.end_gates_batch_for_step()?;
+///
+/// Decode the key or value word at `ptr` and prove it is `data_word`, returns the leading zeroes count.
+///
+/// The type byte is decoded once into the leading zeroes count(the raw word type 0 has none).
+/// The leading zeroes type `t` has `t - 11` and `42 - t` range checked by the byte range lookups, so only
+/// the types 0 and 11..=42 pass. There is no dedicated `type -> count` table, the two lookups in the range
+/// table shared with the bytes decode the type as well.
+/// The data byte `j` is zero if `j < count` by the prefix mask, otherwise it is the compressed byte
+/// `ptr + 1 + j - count`. The masked bytes read the type byte to stay in the array bounds.
+/// The inactive word is decoded as the raw zeroes.
+///
+#[allow(clippy::too_many_arguments)]
+fn decode_word<E: Engine, CS: ConstraintSystem<E>>(
+    cs: &mut CS,
+    compressed_data: &mut DynamicByteArray<E>,
+    ptr: &Num<E>,
+    active: Boolean,
+    data_word: &[Byte<E>],
+    canonical_encoding: bool,
+    range_check: ByteRangeCheck,
+    range_table_name: &str,
+) -> Result<Num<E>, SynthesisError> {
+    let active_num = Num::from_boolean_is(active);
+    let type_byte = compressed_data.read(cs, ptr)?.inner.mul(cs, &active_num)?;
 
+    let is_raw = Num::equals(cs, &type_byte, &Num::Constant(E::Fr::zero()))?;
+    let is_leading_zeroes = boolean_to_num(cs, &is_raw.not())?;
+    let above_min = type_byte
+        .sub(cs, &Num::Constant(fr_from_usize(11)))?
+        .mul(cs, &is_leading_zeroes)?;
+    let below_max = Num::Constant(fr_from_usize(42))
+        .sub(cs, &type_byte)?
+        .mul(cs, &is_leading_zeroes)?;
+    enforce_bytes_range(cs, &[above_min.clone(), below_max], range_check, range_table_name)?;
+    let zeroes = above_min.add(cs, &is_leading_zeroes)?;
 
-        result.push(Byte{inner});
+    // The prefix mask: boolean, non-increasing and with `zeroes` ones, so `mask[j] = j < zeroes`.
+    let mut mask: Vec<Boolean> = Vec::with_capacity(32);
+    let mut ones = LinearCombination::zero();
+    for j in 0..32 {
+        let bit = Boolean::alloc(cs, zeroes.get_value().map(|zeroes| j < fr_to_usize(zeroes)))?;
+        if let Some(previous) = mask.last() {
+            let rise = Boolean::and(cs, &bit, &previous.not())?;
+            Boolean::enforce_equal(cs, &rise, &Boolean::constant(false))?;
+        }
+        ones.add_assign_number_with_coeff(&Num::from_boolean_is(bit), E::Fr::one());
+        mask.push(bit);
     }
+    let mut minus_one = E::Fr::one();
+    minus_one.negate();
+    ones.add_assign_number_with_coeff(&zeroes, minus_one);
+    ones.enforce_zero(cs)?;
 
-    Ok(result)
+    for (j, data_byte) in data_word.iter().enumerate() {
+        let suffix = Num::from_boolean_is(Boolean::and(cs, &mask[j].not(), &active)?);
+        let unmasked = boolean_to_num(cs, &mask[j].not())?;
+        let shift = Num::Constant(fr_from_usize(1 + j)).sub(cs, &zeroes)?.mul(cs, &unmasked)?;
+        let index = ptr.add(cs, &shift)?;
+        let compressed_byte = compressed_data.read(cs, &index)?;
+        let expected = compressed_byte.inner.mul(cs, &suffix)?;
+        data_byte.inner.enforce_equal(cs, &expected)?;
+    }
+
+    if canonical_encoding {
+        // The first byte after the leading zeroes `data_word[zeroes]` is nonzero unless all bytes are zeroes.
+        let mut first = Num::Constant(E::Fr::zero());
+        for (j, data_byte) in data_word.iter().enumerate() {
+            let previous = match j {
+                0 => Num::Constant(E::Fr::one()),
+                _ => Num::from_boolean_is(mask[j - 1]),
+            };
+            let is_first = previous.sub(cs, &Num::from_boolean_is(mask[j]))?;
+            let term = is_first.mul(cs, &data_byte.inner)?;
+            first = first.add(cs, &term)?;
+        }
+        let first_is_zero = Num::equals(cs, &first, &Num::Constant(E::Fr::zero()))?;
+        let not_maximal = Boolean::and(cs, &first_is_zero, &mask[31].not())?;
+        let non_canonical = Boolean::and(cs, &not_maximal, &active)?;
+        Boolean::enforce_equal(cs, &non_canonical, &Boolean::constant(false))?;
+    }
+
+    Ok(zeroes)
 }
 
 
 ///
-/// Read word by dynamic index from bytes array(O(n)).
+/// Allocate the public inputs packing the bytes by `PACKED_INPUT_BYTES` in big endian.
+/// The bytes are range checked, so the packed values are range checked too.
 ///
-fn get_word_from_bytes<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, bytes: &Vec<Byte<E>>, pos: &Num<E>) -> Result<Vec<Byte<E>>, SynthesisError> {
-    let mut result = Vec::with_capacity(33);
-    for index in 0..33 {
-        let zero = Num::alloc(
-            cs,
-            Some(E::Fr::from_str("0").unwrap())
-        )?;
-        let mut index_num = Num::alloc(
-            cs,
-            Some(E::Fr::from_str(&format!("{}", index)).unwrap())
-        )?;
-        index_num = index_num.add(cs, pos)?;
-        let mut res_byte = Byte::from_num(cs, zero)?;
-        for (i, byte) in bytes.iter().enumerate() {
-            let i_num = Num::alloc(
-                cs,
-                Some(E::Fr::from_str(&format!("{}", i)).unwrap())
-            )?;
-            let flag = Num::equals(cs,&i_num, &index_num)?;
-            let mut flag = Num::from_boolean_is(flag);
-            flag = flag.mul(cs, &byte.inner)?;
-            res_byte.inner = res_byte.inner.add(cs, &flag)?;
+pub(crate) fn pack_bytes_as_inputs<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, bytes: &[Byte<E>]) -> Result<(), SynthesisError> {
+    let base = fr_from_usize::<E::Fr>(256);
+    let mut minus_one = E::Fr::one();
+    minus_one.negate();
+
+    for chunk in bytes.chunks(PACKED_INPUT_BYTES) {
+        let mut witness = Some(E::Fr::zero());
+        let mut lc = LinearCombination::zero();
+        let mut coefficient = E::Fr::one();
+        for byte in chunk.iter().rev() {
+            lc.add_assign_number_with_coeff(&byte.inner, coefficient);
+            coefficient.mul_assign(&base);
         }
-        result.push(res_byte);
+        for byte in chunk {
+            witness = witness.zip(byte.inner.get_value()).map(|(mut value, byte)| {
+                value.mul_assign(&base);
+                value.add_assign(&byte);
+                value
+            });
+        }
+
+        let input = AllocatedNum::alloc_input(cs, || witness.grab())?;
+        lc.add_assign_number_with_coeff(&Num::Variable(input), minus_one);
+        lc.enforce_zero(cs)?;
     }
 
-    Ok(result)
-}
\ No newline at end of file
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//!
+//! The proving API: setup, prove and verify over the storage transitions.
+//!
+
+use franklin_crypto::bellman::{
+    compact_bn256::{Bn256, Fr, FrRepr},
+    kate_commitment::{Crs, CrsForMonomialForm},
+    plonk::{
+        better_better_cs::{
+            cs::{Circuit, PlonkCsWidth4WithNextStepParams, ProvingAssembly, Setup, SetupAssembly, Width4MainGateWithDNext},
+            proof::Proof,
+            setup::VerificationKey,
+            verifier,
+        },
+        commitments::transcript::keccak_transcript::RollingKeccakTranscript,
+    },
+    worker::Worker,
+    Field, PrimeField,
+};
+use compression::StorageTransition;
+use compression::sha3;
+use compression::sha3::Digest;
+
+use crate::crs::CrsSource;
+use crate::error::Error;
+use crate::main_circuit::{Capacity, CircuitOptions, CompressionCircuit, PACKED_INPUT_BYTES};
+
+///
+/// The keys needed by the prover, they depend only on the capacity and the circuit options.
+///
+pub struct SetupKeys {
+    pub capacity: Capacity,
+    pub options: CircuitOptions,
+    pub setup: Setup<Bn256, CompressionCircuit<Bn256>>,
+    pub crs: Crs<Bn256, CrsForMonomialForm>,
+}
+
+///
+/// The public inputs of the circuit.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicInputs {
+    /// The keccak256 of the uncompressed data.
+    pub data_hash: [u8; 32],
+    /// The keccak256 of the compressed data.
+    pub compressed_data_hash: [u8; 32],
+}
+
+impl PublicInputs {
+    pub fn new(data: &[u8], compressed_data: &[u8]) -> Self {
+        let mut data_hash = [0u8; 32];
+        data_hash.copy_from_slice(sha3::Keccak256::digest(data).as_slice());
+        let mut compressed_data_hash = [0u8; 32];
+        compressed_data_hash.copy_from_slice(sha3::Keccak256::digest(compressed_data).as_slice());
+
+        Self {
+            data_hash,
+            compressed_data_hash,
+        }
+    }
+
+    ///
+    /// The public inputs for the storage transitions batch.
+    ///
+    pub fn from_transitions(transitions: &[StorageTransition]) -> Self {
+        Self::new(
+            &StorageTransition::into_bytes(transitions.to_vec()),
+            &StorageTransition::compress(transitions.to_vec()),
+        )
+    }
+
+    ///
+    /// Pack the hashes into the field elements the same way as the circuit does.
+    ///
+    pub fn pack(&self) -> PackedPublicInputs {
+        let mut result = vec![Fr::zero(); 4];
+        let chunks = self.compressed_data_hash.chunks(PACKED_INPUT_BYTES).chain(self.data_hash.chunks(PACKED_INPUT_BYTES));
+        for (element, chunk) in result.iter_mut().zip(chunks) {
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(chunk);
+            let value = u128::from_be_bytes(bytes);
+            *element = Fr::from_repr(FrRepr([value as u64, (value >> 64) as u64, 0, 0])).expect("128 bits fit the field");
+        }
+
+        PackedPublicInputs(result)
+    }
+}
+
+///
+/// The public inputs packed into the field elements in the circuit allocation order.
+/// In the `Hashes` mode the 128-bit big endian halves of the compressed data hash, then of the data hash.
+/// In the `Commitment` mode the single commitment.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedPublicInputs(pub Vec<Fr>);
+
+impl PackedPublicInputs {
+    ///
+    /// The public input of the `Commitment` mode circuit.
+    ///
+    pub fn commitment(commitment: Fr) -> Self {
+        Self(vec![commitment])
+    }
+}
+
+impl From<&PublicInputs> for PackedPublicInputs {
+    fn from(public_inputs: &PublicInputs) -> Self {
+        public_inputs.pack()
+    }
+}
+
+///
+/// Generate the setup keys and the verification key for the capacity and the circuit options.
+///
+pub fn setup(capacity: Capacity, options: CircuitOptions, crs: &CrsSource) -> Result<(SetupKeys, VerificationKey<Bn256, CompressionCircuit<Bn256>>), Error> {
+    let worker = Worker::new();
+
+    let mut assembly = SetupAssembly::<Bn256, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>::new();
+    CompressionCircuit::<Bn256>::without_witness(capacity)
+        .with_options(options)
+        .synthesize(&mut assembly)?;
+    assembly.finalize();
+
+    let domain_size = assembly.n().next_power_of_two();
+    let crs = crs.load(domain_size)?;
+
+    let setup = assembly.create_setup::<CompressionCircuit<Bn256>>(&worker)?;
+    let vk = VerificationKey::from_setup(&setup, &worker, &crs)?;
+
+    Ok((SetupKeys { capacity, options, setup, crs }, vk))
+}
+
+///
+/// Prove the compression of the storage transitions batch.
+/// The circuit doesn't decode the keys compressed with the preimage and offset(type 2), the batches with
+/// them are rejected with `Error::UnsupportedEncoding` before the synthesis.
+///
+pub fn prove(keys: &SetupKeys, transitions: &[StorageTransition]) -> Result<Proof<Bn256, CompressionCircuit<Bn256>>, Error> {
+    let worker = Worker::new();
+    let circuit = CompressionCircuit::<Bn256>::from_transitions(keys.capacity, transitions)?
+        .with_options(keys.options);
+
+    let mut assembly = ProvingAssembly::<Bn256, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>::new();
+    circuit.synthesize(&mut assembly)?;
+    assembly.finalize();
+
+    let proof = assembly.create_proof::<CompressionCircuit<Bn256>, RollingKeccakTranscript<Fr>>(
+        &worker,
+        &keys.setup,
+        &keys.crs,
+        None,
+    )?;
+
+    Ok(proof)
+}
+
+///
+/// Verify the proof for the packed public inputs.
+///
+pub fn verify(
+    vk: &VerificationKey<Bn256, CompressionCircuit<Bn256>>,
+    proof: &Proof<Bn256, CompressionCircuit<Bn256>>,
+    public_inputs: &PackedPublicInputs,
+) -> Result<bool, Error> {
+    if proof.inputs != public_inputs.0 {
+        return Ok(false);
+    }
+
+    let valid = verifier::verify::<Bn256, CompressionCircuit<Bn256>, RollingKeccakTranscript<Fr>>(vk, proof, None)?;
+
+    Ok(valid)
+}
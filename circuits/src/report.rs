@@ -0,0 +1,92 @@
+//!
+//! The gates count per circuit section, shows where the circuit cost comes from.
+//!
+
+use std::fmt;
+
+use franklin_crypto::bellman::{
+    compact_bn256::Bn256,
+    plonk::better_better_cs::cs::{ConstraintSystem, PlonkCsWidth4WithNextStepParams, SetupAssembly, Width4MainGateWithDNext},
+    Engine,
+};
+
+use crate::error::Error;
+use crate::main_circuit::{Capacity, CircuitOptions, CompressionCircuit};
+
+/// The bytes allocation with the range checks(`allocate_and_prove_bytes`).
+pub const RANGE_CHECKS: &str = "range checks";
+/// The public inputs packing or the commitment.
+pub const PUBLIC_INPUTS: &str = "public inputs";
+/// The active transition slots flags and the inactive data zeroes.
+pub const ACTIVE_FLAGS: &str = "active flags";
+/// The compressed address words reading by `ptr` and the final `ptr` check.
+pub const WORD_EXTRACTION: &str = "word extraction";
+/// The address words equality chains.
+pub const ADDRESS_DECODING: &str = "address decoding";
+/// The key and value words reading and decoding by the prefix mask.
+pub const KEY_VALUE_DECODING: &str = "key/value decoding";
+/// The memory-consistency argument of the compressed data reads.
+pub const MEMORY_CONSISTENCY: &str = "memory consistency";
+
+///
+/// The gates count per named section in the synthesis order.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GatesReport {
+    pub sections: Vec<(&'static str, usize)>,
+    last: usize,
+}
+
+impl GatesReport {
+    ///
+    /// Start counting from the current gate, the gates before aren't reported.
+    ///
+    pub fn start<E: Engine, CS: ConstraintSystem<E>>(&mut self, cs: &CS) {
+        self.last = cs.get_current_step_number();
+    }
+
+    ///
+    /// Add the gates since the previous record to the section.
+    ///
+    pub fn record<E: Engine, CS: ConstraintSystem<E>>(&mut self, cs: &CS, section: &'static str) {
+        let current = cs.get_current_step_number();
+        let gates = current - self.last;
+        self.last = current;
+
+        match self.sections.iter_mut().find(|(name, _)| *name == section) {
+            Some((_, count)) => *count += gates,
+            None => self.sections.push((section, gates)),
+        }
+    }
+
+    pub fn get(&self, section: &str) -> Option<usize> {
+        self.sections.iter().find(|(name, _)| *name == section).map(|(_, count)| *count)
+    }
+
+    pub fn total(&self) -> usize {
+        self.sections.iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl fmt::Display for GatesReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        for (section, count) in &self.sections {
+            writeln!(f, "{:<20} {:>10} {:>6.2}%", section, count, *count as f64 * 100.0 / total.max(1) as f64)?;
+        }
+        write!(f, "{:<20} {:>10}", "total", total)
+    }
+}
+
+///
+/// The gates report of the circuit with the capacity and options.
+///
+pub fn gates_report(capacity: Capacity, options: CircuitOptions) -> Result<GatesReport, Error> {
+    let mut assembly = SetupAssembly::<Bn256, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>::new();
+    let mut report = GatesReport::default();
+    CompressionCircuit::<Bn256>::without_witness(capacity)
+        .with_options(options)
+        .synthesize_with_report(&mut assembly, &mut report)?;
+
+    Ok(report)
+}
@@ -0,0 +1,372 @@
+//!
+//! The setup keys, verification key and proof serialization.
+//!
+//! Every file starts with the header: magic, version, artifact kind, circuit capacity,
+//! circuit options and the hash of the verification key the artifact belongs to. So the prover and the
+//! verifier can't mix up the keys and proofs of different circuits.
+//!
+//! Binary layout(integers are little endian):
+//! `b"ZKCOMP" | version: u8 | kind: u8 | transitions: u64 | max_compressed_data_size: u64 | public_input_mode: u8 | canonical_encoding: u8 | vk_hash: [u8; 32] | payload`
+//!
+//! The hex format is the binary one in hex, the JSON format is the header with the hex payload.
+//!
+
+use std::io::{Read, Write};
+
+use franklin_crypto::bellman::{
+    compact_bn256::Bn256,
+    kate_commitment::{Crs, CrsForMonomialForm},
+    plonk::better_better_cs::{
+        cs::Setup,
+        proof::Proof,
+        setup::VerificationKey,
+    },
+    worker::Worker,
+};
+use serde::{Deserialize, Serialize};
+use compression::sha3;
+use compression::sha3::Digest;
+
+use crate::error::Error;
+use crate::main_circuit::{Capacity, CircuitOptions, CompressionCircuit, PublicInputMode};
+use crate::proving::SetupKeys;
+
+pub const MAGIC: &[u8; 6] = b"ZKCOMP";
+const VERSION: u8 = 3;
+
+///
+/// The kind of the serialized artifact.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    SetupKeys,
+    VerificationKey,
+    Proof,
+}
+
+impl ArtifactKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            ArtifactKind::SetupKeys => 0,
+            ArtifactKind::VerificationKey => 1,
+            ArtifactKind::Proof => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(ArtifactKind::SetupKeys),
+            1 => Ok(ArtifactKind::VerificationKey),
+            2 => Ok(ArtifactKind::Proof),
+            _ => Err(Error::InvalidFormat(format!("unknown artifact kind {}", byte))),
+        }
+    }
+}
+
+///
+/// The value that can be written to disk with the header.
+///
+pub trait Artifact: Sized {
+    const KIND: ArtifactKind;
+
+    fn write_payload<W: Write>(&self, writer: W) -> std::io::Result<()>;
+
+    fn read_payload<R: Read>(reader: R, header: &Header) -> std::io::Result<Self>;
+
+    ///
+    /// Check the artifact content against its header.
+    ///
+    fn check(&self, _header: &Header) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Artifact for SetupKeys {
+    const KIND: ArtifactKind = ArtifactKind::SetupKeys;
+
+    fn write_payload<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        self.setup.write(&mut writer)?;
+        self.crs.write(&mut writer)
+    }
+
+    fn read_payload<R: Read>(mut reader: R, header: &Header) -> std::io::Result<Self> {
+        let setup = Setup::<Bn256, CompressionCircuit<Bn256>>::read(&mut reader)?;
+        let crs = Crs::<Bn256, CrsForMonomialForm>::read(&mut reader)?;
+
+        Ok(SetupKeys {
+            capacity: header.capacity,
+            options: header.options,
+            setup,
+            crs,
+        })
+    }
+
+    ///
+    /// The verification key is recomputed from the setup, so the keys of another circuit are rejected.
+    ///
+    fn check(&self, header: &Header) -> Result<(), Error> {
+        let vk = VerificationKey::from_setup(&self.setup, &Worker::new(), &self.crs)?;
+        if vk_hash(&vk)? != header.vk_hash {
+            return Err(Error::VerificationKeyMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+impl Artifact for VerificationKey<Bn256, CompressionCircuit<Bn256>> {
+    const KIND: ArtifactKind = ArtifactKind::VerificationKey;
+
+    fn write_payload<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        self.write(writer)
+    }
+
+    fn read_payload<R: Read>(reader: R, _header: &Header) -> std::io::Result<Self> {
+        Self::read(reader)
+    }
+
+    fn check(&self, header: &Header) -> Result<(), Error> {
+        if vk_hash(self)? != header.vk_hash {
+            return Err(Error::VerificationKeyMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+impl Artifact for Proof<Bn256, CompressionCircuit<Bn256>> {
+    const KIND: ArtifactKind = ArtifactKind::Proof;
+
+    fn write_payload<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        self.write(writer)
+    }
+
+    fn read_payload<R: Read>(reader: R, _header: &Header) -> std::io::Result<Self> {
+        Self::read(reader)
+    }
+}
+
+///
+/// The artifact header.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub kind: ArtifactKind,
+    pub capacity: Capacity,
+    pub options: CircuitOptions,
+    pub vk_hash: [u8; 32],
+}
+
+impl Header {
+    pub(crate) fn write<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, self.kind.to_byte()])?;
+        writer.write_all(&(self.capacity.transitions as u64).to_le_bytes())?;
+        writer.write_all(&(self.capacity.max_compressed_data_size as u64).to_le_bytes())?;
+        writer.write_all(&[self.options.public_input_mode.to_byte(), self.options.canonical_encoding as u8])?;
+        writer.write_all(&self.vk_hash)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidFormat("invalid magic".to_owned()));
+        }
+
+        let mut version_and_kind = [0u8; 2];
+        reader.read_exact(&mut version_and_kind)?;
+        if version_and_kind[0] != VERSION {
+            return Err(Error::InvalidFormat(format!("unsupported version {}", version_and_kind[0])));
+        }
+        let kind = ArtifactKind::from_byte(version_and_kind[1])?;
+
+        let mut number = [0u8; 8];
+        reader.read_exact(&mut number)?;
+        let transitions = u64::from_le_bytes(number) as usize;
+        reader.read_exact(&mut number)?;
+        let max_compressed_data_size = u64::from_le_bytes(number) as usize;
+
+        let mut options = [0u8; 2];
+        reader.read_exact(&mut options)?;
+        let options = CircuitOptions {
+            public_input_mode: PublicInputMode::from_byte(options[0])?,
+            canonical_encoding: match options[1] {
+                0 => false,
+                1 => true,
+                byte => return Err(Error::InvalidFormat(format!("invalid canonical encoding flag {}", byte))),
+            },
+        };
+
+        let mut vk_hash = [0u8; 32];
+        reader.read_exact(&mut vk_hash)?;
+
+        Ok(Self {
+            kind,
+            capacity: Capacity { transitions, max_compressed_data_size },
+            options,
+            vk_hash,
+        })
+    }
+
+    pub(crate) fn check(&self, kind: ArtifactKind, capacity: Option<Capacity>, vk_hash: Option<[u8; 32]>) -> Result<(), Error> {
+        if self.kind != kind {
+            return Err(Error::InvalidFormat(format!("expected {:?}, found {:?}", kind, self.kind)));
+        }
+        if let Some(capacity) = capacity.filter(|capacity| *capacity != self.capacity) {
+            return Err(Error::CapacityMismatch { expected: capacity, found: self.capacity });
+        }
+        if vk_hash.map_or(false, |vk_hash| vk_hash != self.vk_hash) {
+            return Err(Error::VerificationKeyMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// The keccak256 of the binary verification key.
+///
+pub fn vk_hash(vk: &VerificationKey<Bn256, CompressionCircuit<Bn256>>) -> Result<[u8; 32], Error> {
+    let mut bytes = Vec::new();
+    vk.write(&mut bytes)?;
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(sha3::Keccak256::digest(&bytes).as_slice());
+    Ok(hash)
+}
+
+///
+/// Write the artifact in the binary format.
+///
+pub fn write<A: Artifact, W: Write>(artifact: &A, capacity: Capacity, options: CircuitOptions, vk_hash: [u8; 32], mut writer: W) -> Result<(), Error> {
+    Header { kind: A::KIND, capacity, options, vk_hash }.write(&mut writer)?;
+    artifact.write_payload(&mut writer)?;
+
+    Ok(())
+}
+
+///
+/// Read the artifact in the binary format, checking the capacity and the verification key hash(if known).
+///
+pub fn read<A: Artifact, R: Read>(mut reader: R, capacity: Capacity, vk_hash: Option<[u8; 32]>) -> Result<(A, Header), Error> {
+    let header = Header::read(&mut reader)?;
+    header.check(A::KIND, Some(capacity), vk_hash)?;
+
+    let artifact = A::read_payload(&mut reader, &header)?;
+    artifact.check(&header)?;
+
+    Ok((artifact, header))
+}
+
+///
+/// Write the verification key, the header contains its own hash.
+///
+pub fn write_verification_key<W: Write>(
+    vk: &VerificationKey<Bn256, CompressionCircuit<Bn256>>,
+    capacity: Capacity,
+    options: CircuitOptions,
+    writer: W,
+) -> Result<(), Error> {
+    write(vk, capacity, options, vk_hash(vk)?, writer)
+}
+
+///
+/// Encode the artifact as hex of the binary format.
+///
+pub fn to_hex<A: Artifact>(artifact: &A, capacity: Capacity, options: CircuitOptions, vk_hash: [u8; 32]) -> Result<String, Error> {
+    let mut bytes = Vec::new();
+    write(artifact, capacity, options, vk_hash, &mut bytes)?;
+
+    Ok(hex::encode(bytes))
+}
+
+///
+/// Decode the artifact from hex of the binary format.
+///
+pub fn from_hex<A: Artifact>(data: &str, capacity: Capacity, vk_hash: Option<[u8; 32]>) -> Result<(A, Header), Error> {
+    let bytes = hex::decode(data.trim().trim_start_matches("0x"))
+        .map_err(|error| Error::InvalidFormat(error.to_string()))?;
+
+    read(bytes.as_slice(), capacity, vk_hash)
+}
+
+///
+/// The JSON form of the artifact.
+///
+#[derive(Serialize, Deserialize)]
+struct JsonArtifact {
+    version: u8,
+    kind: ArtifactKind,
+    capacity: Capacity,
+    options: CircuitOptions,
+    vk_hash: String,
+    payload: String,
+}
+
+///
+/// Encode the artifact as JSON with the readable header and the hex payload.
+///
+pub fn to_json<A: Artifact>(artifact: &A, capacity: Capacity, options: CircuitOptions, vk_hash: [u8; 32]) -> Result<String, Error> {
+    let mut payload = Vec::new();
+    artifact.write_payload(&mut payload)?;
+
+    let json = JsonArtifact {
+        version: VERSION,
+        kind: A::KIND,
+        capacity,
+        options,
+        vk_hash: format!("0x{}", hex::encode(vk_hash)),
+        payload: format!("0x{}", hex::encode(payload)),
+    };
+
+    serde_json::to_string_pretty(&json).map_err(|error| Error::InvalidFormat(error.to_string()))
+}
+
+///
+/// Decode the artifact from JSON, checking the capacity and the verification key hash(if known).
+///
+pub fn from_json<A: Artifact>(data: &str, capacity: Capacity, vk_hash: Option<[u8; 32]>) -> Result<(A, Header), Error> {
+    let json: JsonArtifact = serde_json::from_str(data).map_err(|error| Error::InvalidFormat(error.to_string()))?;
+    if json.version != VERSION {
+        return Err(Error::InvalidFormat(format!("unsupported version {}", json.version)));
+    }
+
+    let mut header_vk_hash = [0u8; 32];
+    hex::decode_to_slice(json.vk_hash.trim_start_matches("0x"), &mut header_vk_hash)
+        .map_err(|error| Error::InvalidFormat(error.to_string()))?;
+    let header = Header {
+        kind: json.kind,
+        capacity: json.capacity,
+        options: json.options,
+        vk_hash: header_vk_hash,
+    };
+    header.check(A::KIND, Some(capacity), vk_hash)?;
+
+    let payload = hex::decode(json.payload.trim_start_matches("0x"))
+        .map_err(|error| Error::InvalidFormat(error.to_string()))?;
+    let artifact = A::read_payload(payload.as_slice(), &header)?;
+    artifact.check(&header)?;
+
+    Ok((artifact, header))
+}
+
+///
+/// Decode the artifact in any of the formats, the capacity is checked if known.
+///
+pub fn decode<A: Artifact>(data: &[u8], capacity: Option<Capacity>, vk_hash: Option<[u8; 32]>) -> Result<(A, Header), Error> {
+    let bytes = if data.starts_with(MAGIC) {
+        data.to_vec()
+    } else {
+        let text = std::str::from_utf8(data).map_err(|error| Error::InvalidFormat(error.to_string()))?;
+        if text.trim_start().starts_with('{') {
+            let json: JsonArtifact = serde_json::from_str(text).map_err(|error| Error::InvalidFormat(error.to_string()))?;
+            return from_json(text, capacity.unwrap_or(json.capacity), vk_hash);
+        }
+        hex::decode(text.trim().trim_start_matches("0x")).map_err(|error| Error::InvalidFormat(error.to_string()))?
+    };
+
+    let header = Header::read(bytes.as_slice())?;
+    read(bytes.as_slice(), capacity.unwrap_or(header.capacity), vk_hash)
+}
@@ -0,0 +1,252 @@
+//!
+//! The state root transition circuit: the decoded storage transitions are applied to the sparse
+//! merkle tree of the slots `(address, key) -> value` with the rescue hashes.
+//!
+//! The address is packed into one field element, the key and the value into two 128-bit halves.
+//! The nonzero value slot leaf is `rescue(address, key_high, key_low, value_high, value_low)`, the
+//! zero value slot is the zero leaf. The leaf index is the low `depth` bits of
+//! `rescue(address, key_high, key_low)` and the node is `rescue(left, right)`.
+//!
+//! The public inputs are the old root, the new root and the compressed data keccak256 packed
+//! into two halves. The keccak256 is not computed in the circuit: its bytes are a prover witness
+//! and nothing binds them to the compressed data, so the proof binds only the roots and the
+//! verifier must not rely on the hash inputs(see `commitment`).
+//!
+
+use franklin_crypto::{
+    bellman::{
+        compact_bn256::{Bn256, Fr},
+        plonk::better_better_cs::cs::{Circuit, ConstraintSystem, Width4MainGateWithDNext},
+        Engine, Field, PrimeField, SynthesisError,
+    },
+    plonk::circuit::{
+        allocated_num::{AllocatedNum, Num},
+        boolean::Boolean,
+        Assignment,
+    },
+};
+use rescue_poseidon::{CircuitGenericSponge, GenericSponge, RescueParams};
+use compression::tree::{SparseMerkleTree, TreeHasher};
+use compression::{sha3, sha3::Digest, StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE, RECORD_SIZE};
+
+use crate::commitment::{circuit_pack_bytes, pack_bytes};
+use crate::error::Error;
+use crate::main_circuit::{allocate_and_prove_bytes, pack_bytes_as_inputs, ByteRangeCheck, Capacity, CompressionCircuit, PACKED_INPUT_BYTES};
+use crate::report::GatesReport;
+use crate::utils::into_bits_le_strict;
+
+///
+/// The rescue hasher of the state tree.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RescueTreeHasher;
+
+impl TreeHasher for RescueTreeHasher {
+    type Hash = Fr;
+
+    fn empty_leaf() -> Fr {
+        Fr::zero()
+    }
+
+    fn leaf(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], value: &[u8; KEY_VALUE_SIZE]) -> Fr {
+        let mut elements = slot_elements(address, key);
+        elements.extend(pack_bytes::<Bn256>(value, PACKED_INPUT_BYTES));
+        rescue_hash(&elements)
+    }
+
+    fn node(left: &Fr, right: &Fr) -> Fr {
+        rescue_hash(&[*left, *right])
+    }
+
+    fn index(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], depth: usize) -> Vec<bool> {
+        assert!(depth <= Fr::NUM_BITS as usize, "The rescue tree depth is at most {}", Fr::NUM_BITS);
+        let index = rescue_hash(&slot_elements(address, key)).into_repr();
+        (0..depth).map(|bit| index.as_ref()[bit / 64] >> (bit % 64) & 1 == 1).collect()
+    }
+}
+
+///
+/// The state tree with the rescue hashes.
+///
+pub type StateTree = SparseMerkleTree<RescueTreeHasher>;
+
+///
+/// The public inputs of the state transition circuit.
+///
+pub fn state_transition_inputs(old_root: Fr, new_root: Fr, compressed_data: &[u8]) -> Vec<Fr> {
+    let mut inputs = vec![old_root, new_root];
+    inputs.extend(pack_bytes::<Bn256>(sha3::Keccak256::digest(compressed_data).as_slice(), PACKED_INPUT_BYTES));
+    inputs
+}
+
+///
+/// The state transition circuit structure.
+///
+pub struct StateTransitionCircuit<E: Engine> {
+    /// The decoding of the storage transitions, its public inputs aren't exposed.
+    pub compression: CompressionCircuit<E>,
+    /// The number of the tree levels above the leaves.
+    pub depth: usize,
+    pub old_root: Option<E::Fr>,
+    /// The slot values before the transitions, `KEY_VALUE_SIZE` bytes per transition slot.
+    pub pre_values: Vec<Option<u8>>,
+    /// The merkle paths per transition slot, the siblings from the leaf level up.
+    pub paths: Vec<Vec<Option<E::Fr>>>,
+}
+
+impl StateTransitionCircuit<Bn256> {
+    ///
+    /// The circuit with witness applying the transitions to the tree.
+    /// The tree is updated only if all the transitions are applied.
+    ///
+    pub fn from_transitions(capacity: Capacity, tree: &mut StateTree, transitions: &[StorageTransition]) -> Result<Self, Error> {
+        let compression = CompressionCircuit::from_transitions(capacity, transitions)?;
+        let old_root = tree.root();
+        let mut updated = tree.clone();
+        let witnesses = updated.apply(transitions)?;
+        *tree = updated;
+
+        let mut circuit = Self::without_witness(capacity, tree.depth());
+        circuit.compression = compression;
+        circuit.old_root = Some(old_root);
+        for (slot, witness) in witnesses.into_iter().enumerate() {
+            for (byte, value) in circuit.pre_values[slot * KEY_VALUE_SIZE..(slot + 1) * KEY_VALUE_SIZE].iter_mut().zip(witness.pre_value) {
+                *byte = Some(value);
+            }
+            circuit.paths[slot] = witness.path.into_iter().map(Some).collect();
+        }
+        // The inactive slots aren't applied, any witness fits.
+        for slot in transitions.len()..capacity.transitions {
+            circuit.pre_values[slot * KEY_VALUE_SIZE..(slot + 1) * KEY_VALUE_SIZE].fill(Some(0));
+            circuit.paths[slot] = vec![Some(Fr::zero()); tree.depth()];
+        }
+
+        Ok(circuit)
+    }
+}
+
+impl<E: Engine> StateTransitionCircuit<E> {
+    ///
+    /// The circuit without witness, enough for the setup generation.
+    ///
+    pub fn without_witness(capacity: Capacity, depth: usize) -> Self {
+        Self {
+            compression: CompressionCircuit::without_witness(capacity),
+            depth,
+            old_root: None,
+            pre_values: vec![None; capacity.transitions * KEY_VALUE_SIZE],
+            paths: vec![vec![None; depth]; capacity.transitions],
+        }
+    }
+}
+
+impl<E: Engine> Circuit<E> for StateTransitionCircuit<E> {
+    type MainGate = Width4MainGateWithDNext;
+
+    fn synthesize<CS: ConstraintSystem<E>>(&self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let range_check = ByteRangeCheck::cheapest::<E, _>(|cs, range_check| self.synthesize_with_range_check(cs, range_check))?;
+        self.synthesize_with_range_check(cs, range_check)
+    }
+}
+
+impl<E: Engine> StateTransitionCircuit<E> {
+    fn synthesize_with_range_check<CS: ConstraintSystem<E>>(&self, cs: &mut CS, range_check: ByteRangeCheck) -> Result<(), SynthesisError> {
+        let transitions = self.compression.capacity.transitions;
+        if self.depth > E::Fr::NUM_BITS as usize
+            || self.pre_values.len() != transitions * KEY_VALUE_SIZE
+            || self.paths.len() != transitions
+            || self.paths.iter().any(|path| path.len() != self.depth) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let decoded = self.compression.decode(cs, range_check, &mut GatesReport::default())?;
+        let pre_values = allocate_and_prove_bytes(&self.pre_values, self.pre_values.len(), cs, range_check, decoded.range_table_name.as_str())?;
+
+        let old_root = AllocatedNum::alloc_input(cs, || self.old_root.grab())?;
+        let mut root = Num::Variable(old_root);
+        for (slot, active) in decoded.active_flags.iter().enumerate() {
+            let record = &decoded.data[slot * RECORD_SIZE..(slot + 1) * RECORD_SIZE];
+            let mut slot_elements = circuit_pack_bytes(cs, &record[..ADDRESS_SIZE], ADDRESS_SIZE)?;
+            slot_elements.extend(circuit_pack_bytes(cs, &record[ADDRESS_SIZE..ADDRESS_SIZE + KEY_VALUE_SIZE], PACKED_INPUT_BYTES)?);
+            let value = circuit_pack_bytes(cs, &record[ADDRESS_SIZE + KEY_VALUE_SIZE..], PACKED_INPUT_BYTES)?;
+            let pre_value = circuit_pack_bytes(cs, &pre_values[slot * KEY_VALUE_SIZE..(slot + 1) * KEY_VALUE_SIZE], PACKED_INPUT_BYTES)?;
+
+            let index = circuit_rescue_hash(cs, &slot_elements)?;
+            let index_bits = into_bits_le_strict(cs, &index)?;
+            let path = self.paths[slot]
+                .iter()
+                .map(|sibling| Num::alloc(cs, *sibling))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // The inactive slot isn't applied, its path isn't checked.
+            let old_leaf = circuit_leaf(cs, &slot_elements, &pre_value)?;
+            let computed_root = circuit_root(cs, old_leaf, &index_bits[..self.depth], &path)?;
+            let difference = computed_root.sub(cs, &root)?.mul(cs, &Num::from_boolean_is(*active))?;
+            difference.enforce_equal(cs, &Num::Constant(E::Fr::zero()))?;
+
+            let new_leaf = circuit_leaf(cs, &slot_elements, &value)?;
+            let new_root = circuit_root(cs, new_leaf, &index_bits[..self.depth], &path)?;
+            root = Num::conditionally_select(cs, active, &new_root, &root)?;
+        }
+
+        let new_root = AllocatedNum::alloc_input(cs, || root.get_value().grab())?;
+        root.enforce_equal(cs, &Num::Variable(new_root))?;
+        // Unconstrained witness, it's not bound to the compressed data.
+        pack_bytes_as_inputs(cs, &decoded.compressed_data_hash)?;
+
+        Ok(())
+    }
+}
+
+fn slot_elements(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE]) -> Vec<Fr> {
+    let mut elements = pack_bytes::<Bn256>(address, ADDRESS_SIZE);
+    elements.extend(pack_bytes::<Bn256>(key, PACKED_INPUT_BYTES));
+    elements
+}
+
+fn rescue_hash(elements: &[Fr]) -> Fr {
+    let params = RescueParams::<Bn256, 2, 3>::default();
+    let mut sponge = GenericSponge::<Bn256, 2, 3>::new();
+    sponge.absorb_multiple(elements, &params);
+    sponge.pad_if_necessary();
+    sponge.squeeze(&params).expect("Always valid after padding")
+}
+
+fn circuit_rescue_hash<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, elements: &[Num<E>]) -> Result<Num<E>, SynthesisError> {
+    let params = RescueParams::<E, 2, 3>::default();
+    let mut sponge = CircuitGenericSponge::<E, 2, 3>::new();
+    for element in elements {
+        sponge.absorb(cs, *element, &params)?;
+    }
+    sponge.pad_if_necessary();
+    Ok(sponge.squeeze_num(cs, &params)?.expect("Always valid after padding"))
+}
+
+///
+/// The slot leaf, zero if both value halves are zero.
+///
+fn circuit_leaf<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, slot_elements: &[Num<E>], value: &[Num<E>]) -> Result<Num<E>, SynthesisError> {
+    let zero = Num::Constant(E::Fr::zero());
+    let high_is_zero = Num::equals(cs, &value[0], &zero)?;
+    let low_is_zero = Num::equals(cs, &value[1], &zero)?;
+    let is_empty = Boolean::and(cs, &high_is_zero, &low_is_zero)?;
+
+    let mut elements = slot_elements.to_vec();
+    elements.extend_from_slice(value);
+    let leaf = circuit_rescue_hash(cs, &elements)?;
+    Num::conditionally_select(cs, &is_empty, &zero, &leaf)
+}
+
+///
+/// The root of the tree with the leaf at the index, the same as `MerkleWitness::root`.
+///
+fn circuit_root<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, leaf: Num<E>, index: &[Boolean], path: &[Num<E>]) -> Result<Num<E>, SynthesisError> {
+    let mut current = leaf;
+    for (is_right, sibling) in index.iter().zip(path) {
+        let left = Num::conditionally_select(cs, is_right, sibling, &current)?;
+        let right = Num::conditionally_select(cs, is_right, &current, sibling)?;
+        current = circuit_rescue_hash(cs, &[left, right])?;
+    }
+
+    Ok(current)
+}
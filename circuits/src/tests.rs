@@ -0,0 +1,772 @@
+use franklin_crypto::bellman::{
+    compact_bn256::{Bn256, Fr},
+    plonk::better_better_cs::cs::{
+        Circuit, ConstraintSystem, PlonkCsWidth4WithNextStepParams, SetupAssembly, TrivialAssembly,
+        Width4MainGateWithDNext,
+    },
+    SynthesisError,
+};
+use franklin_crypto::plonk::circuit::{allocated_num::Num, byte::Byte};
+use compression::{StorageTransition, RECORD_SIZE};
+
+use crate::dynamic_array::{DynamicByteArray, ReadProof};
+use crate::commitment::transitions_commitment;
+use crate::main_circuit::{allocate_and_prove_bytes, ByteRangeCheck, Capacity, CircuitOptions, CompressionCircuit, PublicInputMode};
+use crate::utils::{fr_from_usize, into_bits_le_strict};
+use crate::{state_transition_inputs, StateTransitionCircuit, StateTree};
+use crate::{prove, setup, verify, CrsSource, Error, GatesReport, PackedPublicInputs, PublicInputs};
+use crate::serialization::{self, ArtifactKind, Header};
+
+type Assembly = TrivialAssembly<Bn256, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>;
+
+fn alloc_bytes(cs: &mut Assembly, len: usize) -> Vec<Byte<Bn256>> {
+    (0..len)
+        .map(|index| Byte { inner: Num::alloc(cs, Some(fr_from_usize::<Fr>(index * 7 % 256))).unwrap() })
+        .collect()
+}
+
+fn words_positions(cs: &mut Assembly, words: usize) -> Vec<Num<Bn256>> {
+    (0..words)
+        .map(|word| Num::alloc(cs, Some(fr_from_usize::<Fr>(word * 33))).unwrap())
+        .collect()
+}
+
+///
+/// Gates count of reading `words` 33-byte words from `len` bytes with `DynamicByteArray` and the proof,
+/// with the proof `enforce_consistency` chooses.
+///
+fn dynamic_array_gates(len: usize, words: usize, proof: ReadProof) -> (usize, ReadProof) {
+    let mut cs = Assembly::new();
+    let mut bytes = DynamicByteArray::new(alloc_bytes(&mut cs, len));
+    let start = cs.n();
+    for pos in words_positions(&mut cs, words) {
+        let word = bytes.read_word(&mut cs, &pos, 33).unwrap();
+        for (offset, byte) in word.iter().enumerate() {
+            let pos = crate::utils::fr_to_usize(pos.get_value().unwrap());
+            assert_eq!(byte.inner.get_value(), Some(fr_from_usize((pos + offset) * 7 % 256)));
+        }
+    }
+    let cheapest = bytes.cheapest_proof().unwrap();
+    bytes.enforce_consistency_with(&mut cs, proof).unwrap();
+    assert!(cs.is_satisfied());
+    (cs.n() - start, cheapest)
+}
+
+#[test]
+fn dynamic_array_out_of_bounds() {
+    for proof in [ReadProof::LinearScan, ReadProof::AccessLog] {
+        let mut cs = Assembly::new();
+        let mut bytes = DynamicByteArray::new(alloc_bytes(&mut cs, 40));
+        let pos = Num::alloc(&mut cs, Some(fr_from_usize::<Fr>(10))).unwrap();
+        bytes.read_word(&mut cs, &pos, 33).unwrap();
+        bytes.enforce_consistency_with(&mut cs, proof).unwrap();
+        assert!(!cs.is_satisfied(), "{:?}", proof);
+    }
+}
+
+#[test]
+fn dynamic_array_empty() {
+    let mut cs = Assembly::new();
+    let mut bytes = DynamicByteArray::new(Vec::new());
+    bytes.read(&mut cs, &Num::Constant(fr_from_usize::<Fr>(0))).unwrap();
+    assert!(matches!(bytes.enforce_consistency(&mut cs), Err(SynthesisError::Unsatisfiable)));
+}
+
+#[test]
+fn dynamic_array_gates_benchmark() {
+    // The single transition capacity and bigger ones.
+    for (len, words) in [(132, 3), (528, 12), (2112, 48)] {
+        let (linear_scan, cheapest) = dynamic_array_gates(len, words, ReadProof::LinearScan);
+        let (access_log, _) = dynamic_array_gates(len, words, ReadProof::AccessLog);
+        println!(
+            "Reading {} words from {} bytes: linear scan {} gates, access log {} gates, {:?} is chosen",
+            words, len, linear_scan, access_log, cheapest
+        );
+        let expected = if linear_scan <= access_log { ReadProof::LinearScan } else { ReadProof::AccessLog };
+        assert_eq!(cheapest, expected);
+    }
+}
+
+fn circuit(capacity: Capacity, transitions: &[StorageTransition]) -> CompressionCircuit<Bn256> {
+    CompressionCircuit::from_transitions(capacity, transitions).unwrap()
+}
+
+fn is_satisfied(circuit: CompressionCircuit<Bn256>) -> bool {
+    let mut cs = Assembly::new();
+    circuit.synthesize(&mut cs).is_ok() && cs.is_satisfied()
+}
+
+fn sample_transition() -> StorageTransition {
+    StorageTransition {
+        address: [221, 31, 123, 46, 34, 67, 213, 90, 55, 0, 12, 54, 222, 56, 77, 0, 132, 12, 1, 5],
+        key: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3],
+        value: [34, 1, 123, 23, 44, 65, 78, 66, 34, 0, 0, 0, 234, 0, 0, 243, 0, 0, 22, 0, 0, 0, 234, 0, 65, 0, 0, 0, 65, 0, 4, 43],
+        meta: None
+    }
+}
+
+#[test]
+fn compression_circuit_gates() {
+    let transitions = vec![StorageTransition {
+        address: [221, 31, 123, 46, 34, 67, 213, 90, 55, 0, 12, 54, 222, 56, 77, 0, 132, 12, 1, 5],
+        key: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 43],
+        value: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 46, 123],
+        meta: None
+    }];
+
+    let mut cs = Assembly::new();
+    let mut report = GatesReport::default();
+    circuit(Capacity::new(1), &transitions).synthesize_with_report(&mut cs, &mut report).unwrap();
+    assert!(cs.is_satisfied());
+    assert_eq!(report.total(), cs.n());
+    println!("Compression circuit: {} gates\n{}", cs.n(), report);
+}
+
+/// The stored gates count per section, rewritten only with `UPDATE_GATES_BASELINE`.
+const GATES_BASELINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/gates_baseline.json");
+
+#[test]
+fn gates_regression() {
+    let report = crate::gates_report(Capacity::new(2), CircuitOptions::default()).unwrap();
+    println!("{}", report);
+    let sections: std::collections::BTreeMap<String, usize> = report
+        .sections
+        .iter()
+        .map(|(section, count)| (section.to_string(), *count))
+        .collect();
+
+    if std::env::var_os("UPDATE_GATES_BASELINE").is_some() {
+        std::fs::write(GATES_BASELINE, serde_json::to_string_pretty(&sections).unwrap()).unwrap();
+        println!("The gates baseline is written to {}", GATES_BASELINE);
+        return;
+    }
+    let baseline = std::fs::read_to_string(GATES_BASELINE)
+        .unwrap_or_else(|error| panic!("{}: {}, run with UPDATE_GATES_BASELINE=1 to create it", GATES_BASELINE, error));
+    let baseline: std::collections::BTreeMap<String, usize> = serde_json::from_str(&baseline).unwrap();
+
+    for (section, count) in &sections {
+        let expected = baseline.get(section).copied().unwrap_or(0);
+        assert!(
+            *count <= expected,
+            "`{}` gates regressed: {} > {}, run with UPDATE_GATES_BASELINE=1 if expected",
+            section, count, expected
+        );
+        if *count < expected {
+            println!("`{}` gates improved: {} < {}, run with UPDATE_GATES_BASELINE=1 to update the baseline", section, count, expected);
+        }
+    }
+}
+
+#[test]
+fn compression_circuit_capacity() {
+    let transitions = vec![sample_transition(), sample_transition()];
+    assert!(is_satisfied(circuit(Capacity::new(2), &transitions)));
+
+    assert!(CompressionCircuit::<Bn256>::from_transitions(Capacity::new(1), &transitions).is_err());
+}
+
+#[test]
+fn compression_circuit_without_witness() {
+    let capacity = Capacity::new(2);
+    let mut setup_assembly = SetupAssembly::<Bn256, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>::new();
+    CompressionCircuit::<Bn256>::without_witness(capacity).synthesize(&mut setup_assembly).unwrap();
+
+    let mut cs = Assembly::new();
+    circuit(capacity, &vec![StorageTransition::default(); 2]).synthesize(&mut cs).unwrap();
+    assert!(cs.is_satisfied());
+
+    // The setup must have the same shape as the circuit with witness.
+    assert_eq!(setup_assembly.n(), cs.n());
+}
+
+#[test]
+fn compression_circuit_partial_batch() {
+    let capacity = Capacity::new(3);
+    assert!(is_satisfied(circuit(capacity, &[])));
+    assert!(is_satisfied(circuit(capacity, &[sample_transition()])));
+    assert!(is_satisfied(circuit(capacity, &[sample_transition(), StorageTransition::default()])));
+
+    // The inactive slot must be zeroes.
+    let mut partial = circuit(capacity, &[sample_transition()]);
+    partial.data.extend(StorageTransition::into_bytes(vec![sample_transition()]).into_iter().map(Some));
+    assert!(!is_satisfied(partial));
+
+    // The transitions count must match the compressed data.
+    let mut partial = circuit(capacity, &[sample_transition(), sample_transition()]);
+    partial.transitions_count = Some(fr_from_usize(1));
+    assert!(!is_satisfied(partial));
+}
+
+///
+/// The transition with the array element key, compressed with the preimage and offset(type 2).
+///
+fn meta_transition() -> StorageTransition {
+    let mut preimage = [0u8; 32];
+    preimage[31] = 3;
+    let mut offset = [0u8; 32];
+    offset[31] = 1;
+    StorageTransition {
+        key: compression::slot_from_preimage_and_offset(preimage, offset),
+        meta: Some((preimage, offset)),
+        ..sample_transition()
+    }
+}
+
+#[test]
+#[ignore = "slow in debug builds, run with `cargo test --release -- --ignored`"]
+fn prove_and_verify() {
+    let transitions = vec![sample_transition()];
+    let (keys, vk) = setup(Capacity::new(2), CircuitOptions::default(), &CrsSource::InsecureTest).unwrap();
+    let proof = prove(&keys, &transitions).unwrap();
+
+    assert!(verify(&vk, &proof, &PublicInputs::from_transitions(&transitions).pack()).unwrap());
+    assert!(!verify(&vk, &proof, &PublicInputs::from_transitions(&[StorageTransition::default()]).pack()).unwrap());
+
+    assert!(matches!(
+        prove(&keys, &[sample_transition(), meta_transition()]),
+        Err(Error::UnsupportedEncoding { transition: 1, type_byte: 2 })
+    ));
+}
+
+#[test]
+fn prove_unsupported_encoding() {
+    // The batch is rejected before the synthesis, so the keys are never used.
+    let keys = crate::SetupKeys {
+        capacity: Capacity::new(2),
+        options: CircuitOptions::default(),
+        setup: franklin_crypto::bellman::plonk::better_better_cs::cs::Setup::empty(),
+        crs: franklin_crypto::bellman::kate_commitment::Crs::dummy_crs(1),
+    };
+    assert!(matches!(
+        prove(&keys, &[sample_transition(), meta_transition()]),
+        Err(Error::UnsupportedEncoding { transition: 1, type_byte: 2 })
+    ));
+}
+
+#[test]
+fn unsupported_encoding() {
+    // `prove` builds the circuit by `from_transitions` before the synthesis.
+    assert!(matches!(
+        CompressionCircuit::<Bn256>::from_transitions(Capacity::new(2), &[sample_transition(), meta_transition()]),
+        Err(Error::UnsupportedEncoding { transition: 1, type_byte: 2 })
+    ));
+
+    // The meta is ignored when the key is shorter without it.
+    let transition = StorageTransition { meta: Some(([0xff; 32], [0xff; 32])), ..sample_transition() };
+    assert!(is_satisfied(circuit(Capacity::new(1), &[transition])));
+}
+
+#[test]
+fn serialization_header() {
+    let header = Header {
+        kind: ArtifactKind::Proof,
+        capacity: Capacity::new(4),
+        options: CircuitOptions { public_input_mode: PublicInputMode::Commitment, canonical_encoding: true },
+        vk_hash: [7; 32],
+    };
+    let mut bytes = Vec::new();
+    header.write(&mut bytes).unwrap();
+    let read = Header::read(bytes.as_slice()).unwrap();
+    assert_eq!(read, header);
+
+    assert!(read.check(ArtifactKind::Proof, Some(Capacity::new(4)), Some([7; 32])).is_ok());
+    assert!(read.check(ArtifactKind::Proof, Some(Capacity::new(4)), None).is_ok());
+    assert!(read.check(ArtifactKind::Proof, None, None).is_ok());
+    assert!(matches!(read.check(ArtifactKind::VerificationKey, Some(Capacity::new(4)), None), Err(Error::InvalidFormat(_))));
+    assert!(matches!(read.check(ArtifactKind::Proof, Some(Capacity::new(3)), None), Err(Error::CapacityMismatch { .. })));
+    assert!(matches!(read.check(ArtifactKind::Proof, Some(Capacity::new(4)), Some([8; 32])), Err(Error::VerificationKeyMismatch)));
+
+    bytes[0] = b'X';
+    assert!(matches!(Header::read(bytes.as_slice()), Err(Error::InvalidFormat(_))));
+}
+
+#[test]
+#[ignore = "slow in debug builds, run with `cargo test --release -- --ignored`"]
+fn serialization_roundtrip() {
+    let capacity = Capacity::new(1);
+    let transitions = vec![sample_transition()];
+    let (keys, vk) = setup(capacity, CircuitOptions::default(), &CrsSource::InsecureTest).unwrap();
+    let vk_hash = serialization::vk_hash(&vk).unwrap();
+
+    let mut bytes = Vec::new();
+    serialization::write(&keys, capacity, CircuitOptions::default(), vk_hash, &mut bytes).unwrap();
+    let (keys, _) = serialization::read::<crate::SetupKeys, _>(bytes.as_slice(), capacity, Some(vk_hash)).unwrap();
+
+    // The header claims another verification key, the one recomputed from the setup doesn't match it.
+    let mut forged = Vec::new();
+    serialization::write(&keys, capacity, CircuitOptions::default(), [8; 32], &mut forged).unwrap();
+    assert!(matches!(
+        serialization::read::<crate::SetupKeys, _>(forged.as_slice(), capacity, None),
+        Err(Error::VerificationKeyMismatch)
+    ));
+    let proof = prove(&keys, &transitions).unwrap();
+
+    let mut bytes = Vec::new();
+    serialization::write_verification_key(&vk, capacity, CircuitOptions::default(), &mut bytes).unwrap();
+    let (vk, _) = serialization::read(bytes.as_slice(), capacity, Some(vk_hash)).unwrap();
+
+    let hex = serialization::to_hex(&proof, capacity, CircuitOptions::default(), vk_hash).unwrap();
+    let (proof, _) = serialization::from_hex(&hex, capacity, Some(vk_hash)).unwrap();
+    let json = serialization::to_json(&proof, capacity, CircuitOptions::default(), vk_hash).unwrap();
+    let (proof, _) = serialization::from_json(&json, capacity, Some(vk_hash)).unwrap();
+    assert!(verify(&vk, &proof, &PublicInputs::from_transitions(&transitions).pack()).unwrap());
+
+    assert!(matches!(
+        serialization::from_json::<franklin_crypto::bellman::plonk::better_better_cs::proof::Proof<Bn256, CompressionCircuit<Bn256>>>(&json, Capacity::new(2), None),
+        Err(Error::CapacityMismatch { .. })
+    ));
+}
+
+#[test]
+fn crs_ptau_invalid() {
+    let mut ptau = b"ptau".to_vec();
+    ptau.extend(1u32.to_le_bytes());
+    ptau.extend(0u32.to_le_bytes());
+    assert!(matches!(
+        crate::crs::read_ptau(std::io::Cursor::new(ptau), 16),
+        Err(Error::InvalidFormat(_))
+    ));
+
+    assert!(matches!(
+        crate::crs::read_ptau(std::io::Cursor::new(b"zkey".to_vec()), 16),
+        Err(Error::InvalidFormat(_))
+    ));
+}
+
+#[test]
+fn packed_public_inputs() {
+    let transitions = vec![sample_transition()];
+    let mut cs = Assembly::new();
+    circuit(Capacity::new(1), &transitions).synthesize(&mut cs).unwrap();
+    assert!(cs.is_satisfied());
+
+    let packed = PublicInputs::from_transitions(&transitions).pack();
+    assert_eq!(cs.input_assingments, packed.0.to_vec());
+}
+
+#[test]
+fn commitment_public_input() {
+    let capacity = Capacity::new(2);
+    let transitions = vec![sample_transition()];
+    let mut cs = Assembly::new();
+    circuit(capacity, &transitions)
+        .with_options(CircuitOptions { public_input_mode: PublicInputMode::Commitment, ..CircuitOptions::default() })
+        .synthesize(&mut cs)
+        .unwrap();
+    assert!(cs.is_satisfied());
+
+    let commitment = transitions_commitment(capacity, &transitions);
+    assert_eq!(cs.input_assingments, PackedPublicInputs::commitment(commitment).0);
+    assert_ne!(commitment, transitions_commitment(capacity, &[StorageTransition::default()]));
+    assert_ne!(commitment, transitions_commitment(Capacity::new(3), &transitions));
+}
+
+#[test]
+#[ignore = "slow in debug builds, run with `cargo test --release -- --ignored`"]
+fn prove_and_verify_commitment() {
+    let capacity = Capacity::new(1);
+    let transitions = vec![sample_transition()];
+    let options = CircuitOptions { public_input_mode: PublicInputMode::Commitment, ..CircuitOptions::default() };
+    let (keys, vk) = setup(capacity, options, &CrsSource::InsecureTest).unwrap();
+    let proof = prove(&keys, &transitions).unwrap();
+
+    let commitment = PackedPublicInputs::commitment(transitions_commitment(capacity, &transitions));
+    assert!(verify(&vk, &proof, &commitment).unwrap());
+    assert!(!verify(&vk, &proof, &PublicInputs::from_transitions(&transitions).pack()).unwrap());
+}
+
+///
+/// The sample transition circuit with the mutated witness.
+///
+fn corrupted(mutate: impl FnOnce(&mut CompressionCircuit<Bn256>)) -> CompressionCircuit<Bn256> {
+    let mut circuit = circuit(Capacity::new(2), &[sample_transition()]);
+    mutate(&mut circuit);
+    circuit
+}
+
+///
+/// Replace the compressed data, the length is updated too.
+///
+fn set_compressed_data(circuit: &mut CompressionCircuit<Bn256>, compressed_data: Vec<u8>) {
+    circuit.compressed_data_len = Some(fr_from_usize(compressed_data.len()));
+    circuit.compressed_data = compressed_data.into_iter().map(Some).collect();
+}
+
+fn sample_compressed_data() -> Vec<u8> {
+    // The address(type 1), the key with 31 leading zeroes(type 41), the raw value(type 0).
+    let compressed_data = StorageTransition::compress(vec![sample_transition()]);
+    assert_eq!((compressed_data[0], compressed_data[21], compressed_data[23]), (1, 41, 0));
+    compressed_data
+}
+
+#[test]
+fn soundness_valid_witness() {
+    assert!(is_satisfied(corrupted(|_| {})));
+}
+
+#[test]
+fn soundness_flipped_data_byte() {
+    // The address, the key and the value bytes.
+    for index in [0, 19, 20, 51, 52, 83] {
+        assert!(!is_satisfied(corrupted(|circuit| {
+            circuit.data[index] = circuit.data[index].map(|byte| byte ^ 1);
+        })), "byte {}", index);
+    }
+    // The inactive slot must be zeroes.
+    assert!(!is_satisfied(corrupted(|circuit| {
+        circuit.data.resize(Capacity::new(2).max_uncompressed_data_size(), Some(0));
+        circuit.data[RECORD_SIZE] = Some(1);
+    })));
+}
+
+#[test]
+fn soundness_changed_type_byte() {
+    // The type 10 at 23 is the raw value type 0 alias, the lengths stay consistent.
+    for (position, type_byte) in [(0, 0), (0, 2), (21, 0), (21, 2), (21, 40), (23, 1), (23, 3), (23, 10), (23, 43)] {
+        let mut compressed_data = sample_compressed_data();
+        compressed_data[position] = type_byte;
+        assert!(!is_satisfied(corrupted(|circuit| set_compressed_data(circuit, compressed_data))), "type {} at {}", type_byte, position);
+    }
+}
+
+#[test]
+fn soundness_leading_zeroes_conflict() {
+    // The key claims 32 leading zeroes, but its last byte is 3. The lengths and pointers are consistent.
+    let mut compressed_data = sample_compressed_data();
+    compressed_data[21] = 42;
+    compressed_data.remove(22);
+    assert!(!is_satisfied(corrupted(|circuit| set_compressed_data(circuit, compressed_data))));
+
+    // The value claims a leading zero, but its first byte is nonzero.
+    let mut compressed_data = sample_compressed_data();
+    compressed_data[23] = 11;
+    compressed_data.remove(24);
+    assert!(!is_satisfied(corrupted(|circuit| set_compressed_data(circuit, compressed_data))));
+}
+
+#[test]
+fn soundness_shifted_ptr() {
+    // The extra byte after the address shifts the rest of the words.
+    let mut compressed_data = sample_compressed_data();
+    compressed_data.insert(21, 0);
+    assert!(!is_satisfied(corrupted(|circuit| set_compressed_data(circuit, compressed_data))));
+
+    // The final ptr doesn't match the compressed data length.
+    for delta in [-1, 1] {
+        assert!(!is_satisfied(corrupted(|circuit| {
+            let len = sample_compressed_data().len() as isize + delta;
+            circuit.compressed_data_len = Some(fr_from_usize(len as usize));
+        })), "delta {}", delta);
+    }
+}
+
+#[test]
+fn soundness_truncated_compressed_data() {
+    let compressed_data = sample_compressed_data();
+
+    // The length matches the truncated data.
+    let truncated = compressed_data[..compressed_data.len() - 1].to_vec();
+    assert!(!is_satisfied(corrupted(|circuit| set_compressed_data(circuit, truncated))));
+
+    // The length is the original one, the missing bytes are read as zeroes.
+    assert!(!is_satisfied(corrupted(|circuit| {
+        circuit.compressed_data.truncate(compressed_data.len() - 1);
+    })));
+}
+
+///
+/// The xorshift generator, the differential tests must be reproducible.
+///
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn fill(&mut self, bytes: &mut [u8]) {
+        for byte in bytes {
+            *byte = self.next() as u8;
+        }
+    }
+
+    ///
+    /// The word with the edge cases: all zeroes, all 0xFF or the random leading zeroes count.
+    ///
+    fn word(&mut self) -> [u8; 32] {
+        match self.below(4) {
+            0 => [0; 32],
+            1 => [0xff; 32],
+            _ => {
+                let mut word = [0; 32];
+                let zeroes = self.below(33);
+                self.fill(&mut word[zeroes..]);
+                word
+            },
+        }
+    }
+
+    fn transition(&mut self) -> StorageTransition {
+        let mut address = [0xff; 20];
+        if self.below(4) != 0 {
+            self.fill(&mut address);
+        }
+        let value = self.word();
+
+        if self.below(3) == 0 {
+            // The mapping slot, the offset is small to avoid the overflow.
+            let preimage = self.word();
+            let mut offset = [0; 32];
+            offset[31] = self.next() as u8;
+            StorageTransition {
+                address,
+                key: compression::slot_from_preimage_and_offset(preimage, offset),
+                value,
+                meta: Some((preimage, offset)),
+            }
+        } else {
+            StorageTransition { address, key: self.word(), value, meta: None }
+        }
+    }
+}
+
+///
+/// Whether the batch has the preimage and offset keys(type 2), the circuit doesn't decode them yet.
+///
+fn uses_unsupported_type(transitions: &[StorageTransition]) -> bool {
+    transitions
+        .iter()
+        .any(|transition| StorageTransition::compress(vec![transition.clone()])[21] == 2)
+}
+
+///
+/// Whether `try_uncompress`(or `uncompress_canonical`) reproduces the data from the compressed data.
+///
+fn uncompress_reproduces(compressed_data: &[u8], data: &[u8], canonical: bool) -> bool {
+    let transitions = if canonical {
+        StorageTransition::uncompress_canonical(compressed_data)
+    } else {
+        StorageTransition::try_uncompress(compressed_data)
+    };
+    transitions.map_or(false, |transitions| StorageTransition::into_bytes(transitions) == data)
+}
+
+///
+/// Generate `cases` random batches(with or without the type 2 keys) and random corruptions of their
+/// compressed data, the circuit must be satisfied exactly when `uncompress` reproduces the data.
+///
+fn differential_uncompress_cases(seed: u64, cases: usize, with_type_2: bool) {
+    let capacity = Capacity::new(2);
+    let mut rng = XorShift(seed);
+
+    for case in 0..cases {
+        let transitions = loop {
+            let transitions: Vec<_> = (0..1 + rng.below(capacity.transitions)).map(|_| rng.transition()).collect();
+            if uses_unsupported_type(&transitions) == with_type_2 {
+                break transitions;
+            }
+        };
+        let data = StorageTransition::into_bytes(transitions.clone());
+        let compressed_data = StorageTransition::compress(transitions.clone());
+
+        // The circuit must agree with `uncompress` on the corrupted compressed data too.
+        let mut corrupted_data = compressed_data.clone();
+        let position = rng.below(corrupted_data.len());
+        corrupted_data[position] = rng.next() as u8;
+
+        // `from_transitions` rejects the type 2 keys, the witness is set directly.
+        let without_meta: Vec<_> = transitions.iter().map(|transition| StorageTransition { meta: None, ..transition.clone() }).collect();
+        let with_compressed_data = |compressed_data: &[u8], canonical| {
+            let options = CircuitOptions { canonical_encoding: canonical, ..CircuitOptions::default() };
+            let mut result = circuit(capacity, &without_meta).with_options(options);
+            result.compressed_data = compressed_data.iter().copied().map(Some).collect();
+            result.compressed_data_len = Some(fr_from_usize(compressed_data.len()));
+            result
+        };
+
+        for canonical in [false, true] {
+            assert!(uncompress_reproduces(&compressed_data, &data, canonical), "case {}: {:?}", case, transitions);
+            assert!(is_satisfied(with_compressed_data(&compressed_data, canonical)), "case {}: the circuit rejects {:?}", case, transitions);
+
+            let expected = uncompress_reproduces(&corrupted_data, &data, canonical);
+            let corrupted = with_compressed_data(&corrupted_data, canonical);
+            assert_eq!(
+                is_satisfied(corrupted),
+                expected,
+                "case {}, canonical {}: byte {} of {:?}", case, canonical, position, corrupted_data
+            );
+        }
+    }
+}
+
+#[test]
+fn differential_uncompress() {
+    differential_uncompress_cases(0x5eed_c0de_d00d_f00d, 64, false);
+}
+
+///
+/// The known gap: the circuit rejects every batch with the type 2 keys, while `uncompress` decodes them.
+/// Remove `should_panic` when the circuit decodes the preimage and offset keys.
+///
+#[test]
+#[should_panic(expected = "the circuit rejects")]
+fn differential_uncompress_type_2() {
+    differential_uncompress_cases(0x7e57_0002_d00d_f00d, 64, true);
+}
+
+#[test]
+fn canonical_encoding() {
+    let transition = sample_transition();
+    let non_canonical = [
+        // The key with 30 leading zeroes instead of 31.
+        [&[1][..], &transition.address, &[40, 0, 3], &[0], &transition.value].concat(),
+        // The raw key with the leading zeroes.
+        [&[1][..], &transition.address, &[0], &transition.key, &[0], &transition.value].concat(),
+    ];
+
+    for compressed_data in non_canonical {
+        for canonical in [false, true] {
+            let options = CircuitOptions { canonical_encoding: canonical, ..CircuitOptions::default() };
+            let mut circuit = circuit(Capacity::new(1), &[sample_transition()]).with_options(options);
+            set_compressed_data(&mut circuit, compressed_data.clone());
+            assert_eq!(is_satisfied(circuit), !canonical, "canonical {}: {:?}", canonical, compressed_data);
+        }
+    }
+}
+
+///
+/// Gates count of range checking `len` bytes.
+///
+fn range_check_gates(range_check: ByteRangeCheck, len: usize) -> usize {
+    let mut cs = Assembly::new();
+    let table = range_check.table().unwrap();
+    let table_name = table.functional_name();
+    cs.add_table(table).unwrap();
+
+    let bytes: Vec<_> = (0..len).map(|index| Some((index * 7 % 256) as u8)).collect();
+    allocate_and_prove_bytes(&bytes, len, &mut cs, range_check, &table_name).unwrap();
+    assert!(cs.is_satisfied());
+
+    cs.n()
+}
+
+#[test]
+fn range_check_pairs_gates() {
+    for len in [1, 255, 1000] {
+        let single = range_check_gates(ByteRangeCheck::Single, len);
+        let pairs = range_check_gates(ByteRangeCheck::Pairs, len);
+        println!("Range checks of {} bytes: {} gates with single bytes, {} gates with pairs", len, single, pairs);
+        assert!(pairs <= (single + 1) / 2 + 1);
+    }
+
+
+    // The pairs pay off once the gates outnumber the xor table rows.
+    let cheapest = |gates| ByteRangeCheck::cheapest::<Bn256, _>(|cs, range_check| padded_range_checks(cs, range_check, gates)).unwrap();
+    assert_eq!(cheapest(0), ByteRangeCheck::Single);
+    assert_eq!(cheapest(1 << 17), ByteRangeCheck::Pairs);
+
+    for capacity in [1, 16] {
+        let circuit = CompressionCircuit::<Bn256>::without_witness(Capacity::new(capacity));
+        let rows = |range_check: ByteRangeCheck| {
+            range_check
+                .rows::<Bn256, _>(|cs, range_check| circuit.synthesize_with_range_check(cs, range_check, &mut GatesReport::default()))
+                .unwrap()
+        };
+        let (single, pairs) = (rows(ByteRangeCheck::Single), rows(ByteRangeCheck::Pairs));
+        let chosen = circuit.range_check().unwrap();
+        println!("Capacity {}: {} rows with single bytes, {} rows with pairs, {:?} is chosen", capacity, single, pairs, chosen);
+        assert_eq!(chosen, if pairs < single { ByteRangeCheck::Pairs } else { ByteRangeCheck::Single });
+    }
+}
+
+///
+/// Range check 1000 bytes and add `gates` multiplication gates.
+///
+fn padded_range_checks(
+    cs: &mut SetupAssembly<Bn256, PlonkCsWidth4WithNextStepParams, Width4MainGateWithDNext>,
+    range_check: ByteRangeCheck,
+    gates: usize,
+) -> Result<(), SynthesisError> {
+    let table = range_check.table()?;
+    let table_name = table.functional_name();
+    cs.add_table(table)?;
+    allocate_and_prove_bytes(&vec![None; 1000], 1000, cs, range_check, &table_name)?;
+
+    let mut num = Num::alloc(cs, None)?;
+    for _ in 0..gates {
+        num = num.mul(cs, &num)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn strict_bits_decomposition() {
+    use franklin_crypto::bellman::{Field, PrimeField};
+
+    let mut minus_one = Fr::one();
+    minus_one.negate();
+    for value in [Fr::zero(), fr_from_usize(5), minus_one] {
+        let mut cs = Assembly::new();
+        let num = Num::alloc(&mut cs, Some(value)).unwrap();
+        let bits = into_bits_le_strict(&mut cs, &num).unwrap();
+        assert_eq!(bits.len(), Fr::NUM_BITS as usize);
+        assert!(cs.is_satisfied());
+    }
+}
+
+fn state_transitions() -> Vec<StorageTransition> {
+    let mut transitions = vec![sample_transition(); 4];
+    transitions[1].key[31] = 4;
+    transitions[2].address[0] = 1;
+    // The same slot twice and then deleted.
+    transitions[3].key[31] = 4;
+    transitions[3].value = [0; 32];
+    transitions
+}
+
+fn state_transition_is_satisfied(circuit: StateTransitionCircuit<Bn256>) -> bool {
+    let mut cs = Assembly::new();
+    circuit.synthesize(&mut cs).is_ok() && cs.is_satisfied()
+}
+
+#[test]
+fn state_transition_circuit() {
+    let capacity = Capacity::new(5);
+    let mut tree = StateTree::new(16);
+    let initial = vec![sample_transition()];
+    let first = StateTransitionCircuit::from_transitions(capacity, &mut tree, &initial).unwrap();
+    let old_root = tree.root();
+    assert!(state_transition_is_satisfied(first));
+
+    let transitions = state_transitions();
+    let circuit = StateTransitionCircuit::from_transitions(capacity, &mut tree, &transitions).unwrap();
+    let mut cs = Assembly::new();
+    circuit.synthesize(&mut cs).unwrap();
+    assert!(cs.is_satisfied());
+    assert_eq!(
+        cs.input_assingments,
+        state_transition_inputs(old_root, tree.root(), &StorageTransition::compress(transitions.clone())),
+    );
+
+    let corrupted = |mutate: &dyn Fn(&mut StateTransitionCircuit<Bn256>)| {
+        let mut tree = StateTree::new(16);
+        tree.apply(&initial).unwrap();
+        let mut circuit = StateTransitionCircuit::from_transitions(capacity, &mut tree, &transitions).unwrap();
+        mutate(&mut circuit);
+        state_transition_is_satisfied(circuit)
+    };
+    assert!(corrupted(&|_| {}));
+    assert!(!corrupted(&|circuit| circuit.old_root = Some(Fr::zero())));
+    assert!(!corrupted(&|circuit| circuit.pre_values[31] = Some(0)));
+    assert!(!corrupted(&|circuit| circuit.paths[1][3] = Some(fr_from_usize(1))));
+    // The inactive slot witness isn't checked.
+    assert!(corrupted(&|circuit| circuit.paths[4][3] = Some(fr_from_usize(1))));
+}
@@ -1,3 +1,5 @@
+use franklin_crypto::bellman::{plonk::better_better_cs::cs::ConstraintSystem, Engine, Field, PrimeField, PrimeFieldRepr, SynthesisError};
+use franklin_crypto::plonk::circuit::{allocated_num::Num, boolean::Boolean, linear_combination::LinearCombination};
 
 pub fn get_word_position_in_data(mut index: usize) -> (usize, usize) {
     let k = index / 3;
@@ -20,3 +22,72 @@ pub fn get_word_position_in_data(mut index: usize) -> (usize, usize) {
     };
     (offset, size)
 }
+
+///
+/// Converts the number into the field element.
+///
+pub fn fr_from_usize<F: PrimeField>(value: usize) -> F {
+    F::from_str(&format!("{}", value)).unwrap()
+}
+
+///
+/// Converts the field element into the number, `usize::MAX` if it doesn't fit.
+///
+pub fn fr_to_usize<F: PrimeField>(value: F) -> usize {
+    let repr = value.into_repr();
+    let limbs = repr.as_ref();
+    if limbs[1..].iter().any(|limb| *limb != 0) || limbs[0] > usize::MAX as u64 {
+        usize::MAX
+    } else {
+        limbs[0] as usize
+    }
+}
+
+///
+/// The boolean as the number, the negated bit is computed as `1 - bit`.
+///
+pub fn boolean_to_num<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, boolean: &Boolean) -> Result<Num<E>, SynthesisError> {
+    match boolean {
+        Boolean::Not(_) => Num::Constant(E::Fr::one()).sub(cs, &Num::from_boolean_is(boolean.not())),
+        _ => Ok(Num::from_boolean_is(*boolean)),
+    }
+}
+
+///
+/// Decompose the number into `NUM_BITS` bits, the least significant first.
+/// The bits are compared with the modulus, so the decomposition is unique.
+///
+pub fn into_bits_le_strict<E: Engine, CS: ConstraintSystem<E>>(cs: &mut CS, num: &Num<E>) -> Result<Vec<Boolean>, SynthesisError> {
+    let bits_count = E::Fr::NUM_BITS as usize;
+    let witness = num.get_value().map(|value| value.into_repr());
+
+    let mut bits = Vec::with_capacity(bits_count);
+    let mut lc = LinearCombination::zero();
+    let mut coefficient = E::Fr::one();
+    for bit in 0..bits_count {
+        let value = witness.as_ref().map(|repr| repr.as_ref()[bit / 64] >> (bit % 64) & 1 == 1);
+        let bit = Boolean::alloc(cs, value)?;
+        lc.add_assign_boolean_with_coeff(&bit, coefficient);
+        coefficient.double();
+        bits.push(bit);
+    }
+    let mut minus_one = E::Fr::one();
+    minus_one.negate();
+    lc.add_assign_number_with_coeff(num, minus_one);
+    lc.enforce_zero(cs)?;
+
+    // The bits are at most `modulus - 1`: while the prefix is equal, the zero bit of the bound can't be exceeded.
+    let mut bound = E::Fr::char();
+    bound.sub_noborrow(&1u64.into());
+    let mut equal = Boolean::constant(true);
+    for bit in (0..bits_count).rev() {
+        if bound.as_ref()[bit / 64] >> (bit % 64) & 1 == 1 {
+            equal = Boolean::and(cs, &equal, &bits[bit])?;
+        } else {
+            let greater = Boolean::and(cs, &equal, &bits[bit])?;
+            Boolean::enforce_equal(cs, &greater, &Boolean::constant(false))?;
+        }
+    }
+
+    Ok(bits)
+}
@@ -0,0 +1,339 @@
+//!
+//! The annotated listing of the compressed data: every encoded field with its offset, type byte,
+//! decoded value and the bytes saved versus the raw field.
+//!
+//! The listing stops at the first invalid field, the fields before it are kept, so the listing
+//! shows where the decoding fails.
+//!
+
+use std::fmt;
+
+use super::utils::{self, bytes_to_hex};
+use super::{StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Field {
+    Address,
+    Key,
+    Value,
+}
+
+impl Field {
+    ///
+    /// The size of the raw field.
+    ///
+    pub fn size(self) -> usize {
+        match self {
+            Field::Address => ADDRESS_SIZE,
+            Field::Key | Field::Value => KEY_VALUE_SIZE,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Field::Address => Field::Key,
+            Field::Key => Field::Value,
+            Field::Value => Field::Address,
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Address => write!(f, "address"),
+            Field::Key => write!(f, "key"),
+            Field::Value => write!(f, "value"),
+        }
+    }
+}
+
+///
+/// The meaning of the type byte.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Type 0, the raw 32-byte word.
+    Raw,
+    /// Type 1, the raw 20-byte address.
+    Address,
+    /// Type 2, `keccak256(preimage) + offset`, both with the leading zeroes encoding.
+    PreimageOffset {
+        preimage: [u8; KEY_VALUE_SIZE],
+        offset: [u8; KEY_VALUE_SIZE],
+    },
+    /// Type 3, the reference to the dictionary entry, the decoder doesn't support it.
+    DictionaryRef,
+    /// Types 11..=42, the word with `type - 10` leading zero bytes.
+    LeadingZeroes(u8),
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Raw => write!(f, "raw"),
+            Encoding::Address => write!(f, "address"),
+            Encoding::PreimageOffset { .. } => write!(f, "preimage+offset"),
+            Encoding::DictionaryRef => write!(f, "dictionary ref"),
+            Encoding::LeadingZeroes(zeroes) => write!(f, "{}-zero prefix", zeroes),
+        }
+    }
+}
+
+///
+/// The encoded field.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// The offset of the type byte in the compressed data.
+    pub offset: usize,
+    /// The transition index.
+    pub record: usize,
+    pub field: Field,
+    pub type_byte: u8,
+    pub encoding: Encoding,
+    /// The decoded field, `field.size()` bytes, empty for the dictionary reference.
+    pub value: Vec<u8>,
+    /// The encoded size with the type byte, only the type byte for the dictionary reference.
+    pub size: usize,
+}
+
+impl Instruction {
+    ///
+    /// The bytes saved versus the raw field, negative for the raw words with the type byte.
+    ///
+    pub fn saved(&self) -> isize {
+        self.field.size() as isize - self.size as isize
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#06x}  #{:<4} {:<7} type {:<3} {:<16} {}  {} bytes, saved {}",
+            self.offset,
+            self.record,
+            self.field,
+            self.type_byte,
+            self.encoding.to_string(),
+            bytes_to_hex(&self.value),
+            self.size,
+            self.saved()
+        )?;
+        if let Encoding::PreimageOffset { preimage, offset } = &self.encoding {
+            write!(f, "  (preimage {}, offset {})", bytes_to_hex(preimage), bytes_to_hex(offset))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisassembleError {
+    /// The type byte isn't defined.
+    UnknownType { offset: usize, type_byte: u8 },
+    /// The type is defined, but not supported by the decoder(type 3, the dictionary reference).
+    UnsupportedType { offset: usize, type_byte: u8 },
+    /// The type doesn't fit the field, e.g. the address type for the key.
+    UnexpectedType { offset: usize, field: Field, type_byte: u8 },
+    /// The data ends inside the field.
+    Truncated { offset: usize, field: Field },
+    /// `keccak256(preimage) + offset` overflows.
+    Overflow { offset: usize },
+    /// The data ends inside the transition.
+    IncompleteRecord { record: usize },
+    /// The field is valid, but `compress` encodes it differently, see `StorageTransition::uncompress_canonical`.
+    NonCanonical { offset: usize, field: Field },
+}
+
+impl fmt::Display for DisassembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisassembleError::UnknownType { offset, type_byte } => write!(f, "{:#06x}: unknown type {}", offset, type_byte),
+            DisassembleError::UnsupportedType { offset, type_byte } => write!(f, "{:#06x}: unsupported type {}(dictionary ref)", offset, type_byte),
+            DisassembleError::UnexpectedType { offset, field, type_byte } => {
+                write!(f, "{:#06x}: type {} can't encode the {}", offset, type_byte, field)
+            },
+            DisassembleError::Truncated { offset, field } => write!(f, "{:#06x}: the {} is truncated", offset, field),
+            DisassembleError::Overflow { offset } => write!(f, "{:#06x}: the preimage image plus the offset overflows", offset),
+            DisassembleError::IncompleteRecord { record } => write!(f, "the transition #{} is incomplete", record),
+            DisassembleError::NonCanonical { offset, field } => write!(f, "{:#06x}: the {} encoding is not canonical", offset, field),
+        }
+    }
+}
+
+impl std::error::Error for DisassembleError {}
+
+///
+/// The listing of the compressed data, valid if there is no error.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    pub instructions: Vec<Instruction>,
+    pub error: Option<DisassembleError>,
+}
+
+impl Disassembly {
+    ///
+    /// The bytes saved versus the raw transitions.
+    ///
+    pub fn saved(&self) -> isize {
+        self.instructions.iter().map(Instruction::saved).sum()
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        match &self.error {
+            Some(error) => write!(f, "error: {}", error),
+            None => write!(f, "{} fields, saved {} bytes", self.instructions.len(), self.saved()),
+        }
+    }
+}
+
+///
+/// Disassemble the compressed data of `StorageTransition::compress`.
+///
+pub fn disassemble(data: &[u8]) -> Disassembly {
+    let mut instructions = Vec::new();
+    let mut field = Field::Address;
+    let mut offset = 0;
+    let mut record = 0;
+
+    while offset < data.len() {
+        match decode(data, offset, field) {
+            Ok((type_byte, encoding, value, size)) => {
+                instructions.push(Instruction { offset, record, field, type_byte, encoding, value, size });
+                offset += size;
+                if field == Field::Value {
+                    record += 1;
+                }
+                field = field.next();
+            },
+            Err(error) => {
+                // The dictionary reference is listed, but its size is unknown, so the listing stops at it.
+                if let DisassembleError::UnsupportedType { type_byte, .. } = error {
+                    let encoding = Encoding::DictionaryRef;
+                    instructions.push(Instruction { offset, record, field, type_byte, encoding, value: Vec::new(), size: 1 });
+                }
+                return Disassembly { instructions, error: Some(error) };
+            },
+        }
+    }
+
+    let error = (field != Field::Address).then_some(DisassembleError::IncompleteRecord { record });
+    Disassembly { instructions, error }
+}
+
+impl StorageTransition {
+    ///
+    /// Uncompress storage transitions, the same as `uncompress`, but the invalid compressed data is the error.
+    ///
+    pub fn try_uncompress(data: &[u8]) -> Result<Vec<Self>, DisassembleError> {
+        let disassembly = disassemble(data);
+        if let Some(error) = disassembly.error {
+            return Err(error);
+        }
+
+        Ok(Self::from_instructions(&disassembly.instructions))
+    }
+
+    ///
+    /// Uncompress storage transitions accepting only the encodings `compress` produces:
+    /// the raw word starts with nonzero byte, the leading zeroes count is maximal
+    /// and the preimage with offset is shorter than the key with leading zeroes.
+    ///
+    pub fn uncompress_canonical(data: &[u8]) -> Result<Vec<Self>, DisassembleError> {
+        let disassembly = disassemble(data);
+        if let Some(error) = disassembly.error {
+            return Err(error);
+        }
+
+        for instruction in &disassembly.instructions {
+            let canonical = match instruction.encoding {
+                Encoding::Raw => instruction.value[0] != 0,
+                Encoding::Address | Encoding::DictionaryRef => true,
+                Encoding::LeadingZeroes(_) => instruction.size == leading_zeroes_size(&instruction.value),
+                Encoding::PreimageOffset { preimage, offset } => {
+                    instruction.size == 1 + leading_zeroes_size(&preimage) + leading_zeroes_size(&offset)
+                        && instruction.size < leading_zeroes_size(&instruction.value)
+                },
+            };
+            if !canonical {
+                return Err(DisassembleError::NonCanonical { offset: instruction.offset, field: instruction.field });
+            }
+        }
+
+        Ok(Self::from_instructions(&disassembly.instructions))
+    }
+
+    fn from_instructions(instructions: &[Instruction]) -> Vec<Self> {
+        instructions
+            .chunks_exact(3)
+            .map(|fields| {
+                let mut transition = Self::default();
+                transition.address.copy_from_slice(&fields[0].value);
+                transition.key.copy_from_slice(&fields[1].value);
+                transition.value.copy_from_slice(&fields[2].value);
+                transition
+            })
+            .collect()
+    }
+}
+
+///
+/// The size of the word with the maximal leading zeroes count.
+///
+fn leading_zeroes_size(word: &[u8]) -> usize {
+    1 + word.iter().skip_while(|byte| **byte == 0).count()
+}
+
+type Decoded = (u8, Encoding, Vec<u8>, usize);
+
+fn decode(data: &[u8], offset: usize, field: Field) -> Result<Decoded, DisassembleError> {
+    let type_byte = data[offset];
+    let truncated = DisassembleError::Truncated { offset, field };
+    let unexpected = DisassembleError::UnexpectedType { offset, field, type_byte };
+    match (type_byte, field) {
+        (1, Field::Address) => {
+            let value = data.get(offset + 1..offset + 1 + ADDRESS_SIZE).ok_or(truncated)?;
+            Ok((type_byte, Encoding::Address, value.to_vec(), 1 + ADDRESS_SIZE))
+        },
+        (_, Field::Address) | (1, _) => Err(unexpected),
+        (0, _) => {
+            let value = data.get(offset + 1..offset + 1 + KEY_VALUE_SIZE).ok_or(truncated)?;
+            Ok((type_byte, Encoding::Raw, value.to_vec(), 1 + KEY_VALUE_SIZE))
+        },
+        (2, Field::Key) => {
+            let (preimage, preimage_size) = leading_zeroes(data, offset + 1, field)?;
+            let (image_offset, offset_size) = leading_zeroes(data, offset + 1 + preimage_size, field)?;
+            let key = utils::checked_slot_from_preimage_and_offset(preimage, image_offset)
+                .ok_or(DisassembleError::Overflow { offset })?;
+            let encoding = Encoding::PreimageOffset { preimage, offset: image_offset };
+            Ok((type_byte, encoding, key.to_vec(), 1 + preimage_size + offset_size))
+        },
+        (2, _) => Err(unexpected),
+        (3, _) => Err(DisassembleError::UnsupportedType { offset, type_byte }),
+        (11..=42, _) => {
+            let (value, size) = leading_zeroes(data, offset, field)?;
+            Ok((type_byte, Encoding::LeadingZeroes(type_byte - 10), value.to_vec(), size))
+        },
+        _ => Err(DisassembleError::UnknownType { offset, type_byte }),
+    }
+}
+
+///
+/// The leading zeroes word at the offset and its encoded size.
+///
+fn leading_zeroes(data: &[u8], offset: usize, field: Field) -> Result<([u8; KEY_VALUE_SIZE], usize), DisassembleError> {
+    let type_byte = *data.get(offset).ok_or(DisassembleError::Truncated { offset, field })?;
+    if !(11..=10 + KEY_VALUE_SIZE as u8).contains(&type_byte) {
+        return Err(DisassembleError::UnknownType { offset, type_byte });
+    }
+    let size = 1 + KEY_VALUE_SIZE - (type_byte - 10) as usize;
+    let slice = data.get(offset..offset + size).ok_or(DisassembleError::Truncated { offset, field })?;
+    Ok((utils::uncompress_leading_zeroes(slice).0, size))
+}
@@ -0,0 +1,232 @@
+//!
+//! The storage transitions meta from the solc storage layouts(`solc --storage-layout`).
+//!
+//! The elements of the dynamic arrays and long bytes/strings are stored at `keccak256(slot) + offset`,
+//! so their keys are encoded with the preimage and the offset. The mapping value slot is
+//! `keccak256(key . slot)`, its 64-byte preimage can't be encoded, but with the known mapping keys
+//! the dynamic arrays and bytes inside the mapping values are found too.
+//!
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::utils::{keccak, JsonError};
+use super::{StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE};
+
+/// The max number of the offset bytes, the bigger offsets aren't array elements.
+pub const MAX_OFFSET_BYTES: usize = 8;
+/// The max number of the nested mappings searched with the known mapping keys.
+pub const MAX_MAPPING_DEPTH: usize = 2;
+
+type Meta = ([u8; KEY_VALUE_SIZE], [u8; KEY_VALUE_SIZE]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Inplace,
+    Mapping,
+    DynamicArray,
+    Bytes,
+}
+
+#[derive(Debug, Clone)]
+struct Variable {
+    slot: [u8; KEY_VALUE_SIZE],
+    type_id: String,
+}
+
+#[derive(Debug, Clone)]
+struct Type {
+    encoding: Encoding,
+    /// The struct members, the slots are relative to the struct slot.
+    members: Vec<Variable>,
+    /// The mapping value type.
+    value: Option<String>,
+}
+
+///
+/// The storage layout of the contract.
+///
+#[derive(Debug, Clone)]
+pub struct StorageLayout {
+    storage: Vec<Variable>,
+    types: HashMap<String, Type>,
+}
+
+impl StorageLayout {
+    ///
+    /// Parse the `storageLayout` object: `{"storage": [..], "types": {..}}`.
+    ///
+    pub fn from_json(json: &str) -> Result<Self, JsonError> {
+        let layout: Value = serde_json::from_str(json)?;
+        let storage = variables(layout.get("storage"))?;
+
+        let mut types = HashMap::new();
+        match layout.get("types") {
+            None | Some(Value::Null) => {},
+            Some(Value::Object(entries)) => {
+                for (type_id, entry) in entries {
+                    let encoding = match entry.get("encoding").and_then(Value::as_str) {
+                        Some("inplace") => Encoding::Inplace,
+                        Some("mapping") => Encoding::Mapping,
+                        Some("dynamic_array") => Encoding::DynamicArray,
+                        Some("bytes") => Encoding::Bytes,
+                        _ => return Err(JsonError::invalid(&format!("invalid encoding of {}", type_id))),
+                    };
+                    let value = entry.get("value").and_then(Value::as_str).map(str::to_owned);
+                    types.insert(type_id.clone(), Type { encoding, members: variables(entry.get("members"))?, value });
+                }
+            },
+            Some(_) => return Err(JsonError::invalid("expected the types object")),
+        }
+
+        Ok(Self { storage, types })
+    }
+
+    ///
+    /// The meta of the key: the preimage slot and the offset of the dynamic array or bytes element.
+    /// The mapping keys are 32-byte words(the value types are left padded with zeroes).
+    ///
+    pub fn infer_meta(&self, key: &[u8; KEY_VALUE_SIZE], mapping_keys: &[[u8; KEY_VALUE_SIZE]]) -> Option<Meta> {
+        self.storage
+            .iter()
+            .find_map(|variable| self.infer_in_type(&variable.type_id, variable.slot, key, mapping_keys, 0))
+    }
+
+    fn infer_in_type(
+        &self,
+        type_id: &str,
+        slot: [u8; KEY_VALUE_SIZE],
+        key: &[u8; KEY_VALUE_SIZE],
+        mapping_keys: &[[u8; KEY_VALUE_SIZE]],
+        depth: usize,
+    ) -> Option<Meta> {
+        let layout_type = self.types.get(type_id)?;
+        match layout_type.encoding {
+            Encoding::Inplace => layout_type.members.iter().find_map(|member| {
+                self.infer_in_type(&member.type_id, add(&slot, &member.slot)?, key, mapping_keys, depth)
+            }),
+            Encoding::DynamicArray | Encoding::Bytes => {
+                let data = keccak(&[&slot]);
+                if key < &data {
+                    return None;
+                }
+                let offset = sub(key, &data);
+                if offset[..KEY_VALUE_SIZE - MAX_OFFSET_BYTES].iter().any(|byte| *byte != 0) {
+                    return None;
+                }
+                Some((slot, offset))
+            },
+            Encoding::Mapping if depth < MAX_MAPPING_DEPTH => {
+                let value = layout_type.value.as_ref()?;
+                mapping_keys.iter().find_map(|mapping_key| {
+                    self.infer_in_type(value, keccak(&[mapping_key, &slot]), key, mapping_keys, depth + 1)
+                })
+            },
+            Encoding::Mapping => None,
+        }
+    }
+}
+
+///
+/// The storage layouts of the known contracts.
+///
+#[derive(Debug, Clone, Default)]
+pub struct StorageLayouts {
+    layouts: HashMap<[u8; ADDRESS_SIZE], StorageLayout>,
+}
+
+impl StorageLayouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, address: [u8; ADDRESS_SIZE], layout: StorageLayout) {
+        self.layouts.insert(address, layout);
+    }
+
+    ///
+    /// The meta of the contract storage key, `None` for the unknown contracts.
+    ///
+    pub fn infer_meta(&self, address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], mapping_keys: &[[u8; KEY_VALUE_SIZE]]) -> Option<Meta> {
+        self.layouts.get(address)?.infer_meta(key, mapping_keys)
+    }
+
+    ///
+    /// Set the inferred meta of the transitions without meta.
+    ///
+    pub fn annotate(&self, transitions: &mut [StorageTransition], mapping_keys: &[[u8; KEY_VALUE_SIZE]]) {
+        for transition in transitions.iter_mut().filter(|transition| transition.meta.is_none()) {
+            transition.meta = self.infer_meta(&transition.address, &transition.key, mapping_keys);
+        }
+    }
+}
+
+fn variables(entries: Option<&Value>) -> Result<Vec<Variable>, JsonError> {
+    let entries = match entries {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::Array(entries)) => entries,
+        Some(_) => return Err(JsonError::invalid("expected the variables array")),
+    };
+
+    entries
+        .iter()
+        .map(|entry| {
+            let slot = entry.get("slot").and_then(Value::as_str).and_then(slot_from_decimal);
+            let type_id = entry.get("type").and_then(Value::as_str);
+            match (slot, type_id) {
+                (Some(slot), Some(type_id)) => Ok(Variable { slot, type_id: type_id.to_owned() }),
+                _ => Err(JsonError::invalid(&format!("invalid variable {}", entry))),
+            }
+        })
+        .collect()
+}
+
+fn slot_from_decimal(decimal: &str) -> Option<[u8; KEY_VALUE_SIZE]> {
+    if decimal.is_empty() {
+        return None;
+    }
+
+    let mut result = [0u8; KEY_VALUE_SIZE];
+    for digit in decimal.chars() {
+        let mut carry = digit.to_digit(10)?;
+        for byte in result.iter_mut().rev() {
+            carry += *byte as u32 * 10;
+            *byte = (carry % 256) as u8;
+            carry /= 256;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some(result)
+}
+
+///
+/// `a + b`, `None` on the overflow.
+///
+fn add(a: &[u8; KEY_VALUE_SIZE], b: &[u8; KEY_VALUE_SIZE]) -> Option<[u8; KEY_VALUE_SIZE]> {
+    let mut result = [0u8; KEY_VALUE_SIZE];
+    let mut carry = 0u16;
+    for index in (0..KEY_VALUE_SIZE).rev() {
+        carry += a[index] as u16 + b[index] as u16;
+        result[index] = carry as u8;
+        carry >>= 8;
+    }
+    (carry == 0).then_some(result)
+}
+
+///
+/// `a - b` for `a >= b`.
+///
+fn sub(a: &[u8; KEY_VALUE_SIZE], b: &[u8; KEY_VALUE_SIZE]) -> [u8; KEY_VALUE_SIZE] {
+    let mut result = [0u8; KEY_VALUE_SIZE];
+    let mut borrow = 0i16;
+    for index in (0..KEY_VALUE_SIZE).rev() {
+        let mut difference = a[index] as i16 - b[index] as i16 - borrow;
+        borrow = (difference < 0) as i16;
+        difference += borrow * 256;
+        result[index] = difference as u8;
+    }
+    result
+}
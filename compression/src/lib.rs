@@ -2,13 +2,33 @@
 //! The circuit free implementation of compressing and uncompressing
 //!
 mod utils;
+pub mod tree;
+pub mod disassemble;
+pub mod stats;
+pub mod workload;
+#[cfg(feature = "json")]
+pub mod prestate;
+#[cfg(feature = "json")]
+pub mod layout;
+#[cfg(feature = "serde")]
+pub mod serde_support;
 #[cfg(test)]
 mod tests;
 
 pub const ADDRESS_SIZE: usize = 20;
 pub const KEY_VALUE_SIZE: usize = 32;
+/// The size of the uncompressed storage transition record.
+pub const RECORD_SIZE: usize = ADDRESS_SIZE + 2 * KEY_VALUE_SIZE;
+/// The max size of the compressed storage transition record(every field with the type byte).
+pub const MAX_COMPRESSED_RECORD_SIZE: usize = ADDRESS_SIZE + 2 * KEY_VALUE_SIZE + 3;
 
 pub use sha3;
+pub use utils::slot_from_preimage_and_offset;
+pub use disassemble::disassemble;
+pub use stats::CompressionStats;
+pub use workload::{WorkloadGenerator, WorkloadMix};
+#[cfg(feature = "json")]
+pub use utils::{bytes_from_hex, JsonError};
 
 ///
 /// Storage transition rust representation.
@@ -25,12 +45,55 @@ pub struct StorageTransition {
     pub meta: Option<([u8; KEY_VALUE_SIZE], [u8; KEY_VALUE_SIZE])>,
 }
 
+///
+/// The uncompressed data length isn't a multiple of `RECORD_SIZE`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordsLengthError {
+    pub length: usize,
+}
+
+impl std::fmt::Display for RecordsLengthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the size {} is not a multiple of {}", self.length, RECORD_SIZE)
+    }
+}
+
+impl std::error::Error for RecordsLengthError {}
+
+///
+/// The iterator over the uncompressed records, see `StorageTransition::iter_bytes`.
+///
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    records: std::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for Records<'_> {
+    type Item = StorageTransition;
+
+    fn next(&mut self) -> Option<StorageTransition> {
+        let record = self.records.next()?;
+        let mut transition = StorageTransition::default();
+        transition.address.copy_from_slice(&record[..ADDRESS_SIZE]);
+        transition.key.copy_from_slice(&record[ADDRESS_SIZE..ADDRESS_SIZE + KEY_VALUE_SIZE]);
+        transition.value.copy_from_slice(&record[ADDRESS_SIZE + KEY_VALUE_SIZE..]);
+        Some(transition)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Records<'_> {}
+
 impl StorageTransition {
     ///
     /// Converts storage transitions array into bytes.
     ///
     pub fn into_bytes(transitions: Vec<Self>) -> Vec<u8> {
-        let mut result = Vec::with_capacity(transitions.len() * (2*KEY_VALUE_SIZE + ADDRESS_SIZE));
+        let mut result = Vec::with_capacity(transitions.len() * RECORD_SIZE);
 
         for transition in transitions {
             result.extend(transition.address);
@@ -41,6 +104,24 @@ impl StorageTransition {
         result
     }
 
+    ///
+    /// Converts bytes of `into_bytes` back into storage transitions, the meta isn't recorded.
+    ///
+    pub fn from_bytes(data: &[u8]) -> Result<Vec<Self>, RecordsLengthError> {
+        Ok(Self::iter_bytes(data)?.collect())
+    }
+
+    ///
+    /// Iterates over the storage transitions of the bytes of `into_bytes` without copying the bytes.
+    ///
+    pub fn iter_bytes(data: &[u8]) -> Result<Records<'_>, RecordsLengthError> {
+        if !data.len().is_multiple_of(RECORD_SIZE) {
+            return Err(RecordsLengthError { length: data.len() });
+        }
+
+        Ok(Records { records: data.chunks_exact(RECORD_SIZE) })
+    }
+
     ///
     /// Compress storage transitions
     ///
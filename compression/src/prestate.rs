@@ -0,0 +1,111 @@
+//!
+//! The storage transitions from the geth `prestateTracer` traces in `diffMode`.
+//!
+//! The trace of the transaction is `{"pre": {..}, "post": {..}}`, both map the account address to
+//! the account state with the `storage` map. The `pre` storage has the old values of the changed
+//! slots, the `post` storage has the new nonzero values. So the slot only in `pre` is deleted(the
+//! new value is zero) and the slot only in `post` is created. The block trace of
+//! `debug_traceBlockByNumber` is the array of `{"txHash": .., "result": {..}}`, the transactions
+//! are applied in order.
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::Value;
+
+use super::utils::{bytes_from_hex, JsonError};
+use super::{StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE};
+
+type Storage = BTreeMap<[u8; KEY_VALUE_SIZE], [u8; KEY_VALUE_SIZE]>;
+
+impl StorageTransition {
+    ///
+    /// Parse the storage transitions of the `prestateTracer` diff: the single transaction trace,
+    /// the block trace or the JSON-RPC response with either of them as the `result`.
+    /// The transitions of every transaction are ordered by the address and the key.
+    ///
+    pub fn from_prestate_diff(json: &str) -> Result<Vec<Self>, JsonError> {
+        let trace: Value = serde_json::from_str(json)?;
+        let mut transitions = Vec::new();
+        collect_transitions(&trace, &mut transitions)?;
+        Ok(transitions)
+    }
+}
+
+fn collect_transitions(trace: &Value, transitions: &mut Vec<StorageTransition>) -> Result<(), JsonError> {
+    match trace {
+        Value::Array(traces) => {
+            for trace in traces {
+                collect_transitions(trace, transitions)?;
+            }
+            Ok(())
+        },
+        Value::Object(object) if object.contains_key("pre") || object.contains_key("post") => {
+            transaction_transitions(trace, transitions)
+        },
+        Value::Object(object) => match object.get("result") {
+            Some(result) => collect_transitions(result, transitions),
+            None => Err(JsonError::invalid("expected the `pre` and `post` or the `result`")),
+        },
+        _ => Err(JsonError::invalid("expected the trace object or array")),
+    }
+}
+
+fn transaction_transitions(trace: &Value, transitions: &mut Vec<StorageTransition>) -> Result<(), JsonError> {
+    let pre = accounts_storage(trace.get("pre"))?;
+    let post = accounts_storage(trace.get("post"))?;
+
+    let addresses: BTreeSet<_> = pre.keys().chain(post.keys()).collect();
+    for address in addresses {
+        let empty = Storage::new();
+        let pre_storage = pre.get(address).unwrap_or(&empty);
+        let post_storage = post.get(address).unwrap_or(&empty);
+
+        let keys: BTreeSet<_> = pre_storage.keys().chain(post_storage.keys()).collect();
+        for key in keys {
+            let value = post_storage.get(key).copied().unwrap_or([0; KEY_VALUE_SIZE]);
+            if pre_storage.get(key).copied().unwrap_or([0; KEY_VALUE_SIZE]) == value {
+                continue;
+            }
+
+            transitions.push(StorageTransition {
+                address: *address,
+                key: *key,
+                value,
+                meta: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn accounts_storage(accounts: Option<&Value>) -> Result<BTreeMap<[u8; ADDRESS_SIZE], Storage>, JsonError> {
+    let mut result = BTreeMap::new();
+    let accounts = match accounts {
+        None | Some(Value::Null) => return Ok(result),
+        Some(Value::Object(accounts)) => accounts,
+        Some(_) => return Err(JsonError::invalid("expected the accounts object")),
+    };
+
+    for (address, account) in accounts {
+        let address = bytes_from_hex(address).ok_or_else(|| JsonError::invalid(&format!("invalid address {}", address)))?;
+        let mut storage = Storage::new();
+        match account.get("storage") {
+            None | Some(Value::Null) => {},
+            Some(Value::Object(slots)) => {
+                for (key, value) in slots {
+                    let value = value.as_str().and_then(bytes_from_hex);
+                    match (bytes_from_hex(key), value) {
+                        (Some(key), Some(value)) => storage.insert(key, value),
+                        _ => return Err(JsonError::invalid(&format!("invalid storage slot {}", key))),
+                    };
+                }
+            },
+            Some(_) => return Err(JsonError::invalid("expected the storage object")),
+        }
+        result.insert(address, storage);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,131 @@
+//!
+//! The serde support of `StorageTransition` and the JSON Lines format of the transitions batches.
+//!
+//! The transition is `{"address": "0x..", "key": "0x..", "value": "0x..", "meta": {"preimage": "0x..", "offset": "0x.."}}`,
+//! the meta is omitted when there is none. The shorter hex strings are padded with the leading
+//! zeroes and the meta is also accepted as the `["0x..", "0x.."]` pair.
+//!
+
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::utils::{bytes_from_hex, bytes_to_hex};
+use super::{StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE};
+
+///
+/// The bytes as the 0x-prefixed hex string.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Hex<const N: usize>([u8; N]);
+
+impl<const N: usize> Serialize for Hex<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&bytes_to_hex(&self.0))
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for Hex<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        bytes_from_hex(&hex)
+            .map(Hex)
+            .ok_or_else(|| de::Error::custom(format!("`{}` is not the 0x-prefixed hex of at most {} bytes", hex, N)))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    preimage: Hex<KEY_VALUE_SIZE>,
+    offset: Hex<KEY_VALUE_SIZE>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MetaForm {
+    Typed(Meta),
+    Pair(Hex<KEY_VALUE_SIZE>, Hex<KEY_VALUE_SIZE>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Transition {
+    address: Hex<ADDRESS_SIZE>,
+    key: Hex<KEY_VALUE_SIZE>,
+    value: Hex<KEY_VALUE_SIZE>,
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_meta")]
+    meta: Option<Meta>,
+}
+
+fn deserialize_meta<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Meta>, D::Error> {
+    Ok(Option::<MetaForm>::deserialize(deserializer)?.map(|meta| match meta {
+        MetaForm::Typed(meta) => meta,
+        MetaForm::Pair(preimage, offset) => Meta { preimage, offset },
+    }))
+}
+
+impl Serialize for StorageTransition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Transition {
+            address: Hex(self.address),
+            key: Hex(self.key),
+            value: Hex(self.value),
+            meta: self.meta.map(|(preimage, offset)| Meta { preimage: Hex(preimage), offset: Hex(offset) }),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StorageTransition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let transition = Transition::deserialize(deserializer)?;
+        Ok(StorageTransition {
+            address: transition.address.0,
+            key: transition.key.0,
+            value: transition.value.0,
+            meta: transition.meta.map(|meta| (meta.preimage.0, meta.offset.0)),
+        })
+    }
+}
+
+///
+/// The JSON Lines parsing error with the 1-based line number.
+///
+#[derive(Debug)]
+pub struct JsonLinesError {
+    pub line: usize,
+    pub error: serde_json::Error,
+}
+
+impl fmt::Display for JsonLinesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for JsonLinesError {}
+
+impl StorageTransition {
+    ///
+    /// One JSON transition per line, every line ends with the newline.
+    ///
+    pub fn to_json_lines(transitions: &[Self]) -> String {
+        let mut result = String::new();
+        for transition in transitions {
+            result.push_str(&serde_json::to_string(transition).expect("The transition is always serializable"));
+            result.push('\n');
+        }
+        result
+    }
+
+    ///
+    /// Parse one JSON transition per line, the empty lines are skipped.
+    ///
+    pub fn from_json_lines(data: &str) -> Result<Vec<Self>, JsonLinesError> {
+        data.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| serde_json::from_str(line).map_err(|error| JsonLinesError { line: index + 1, error }))
+            .collect()
+    }
+}
@@ -0,0 +1,153 @@
+//!
+//! The compression statistics per type byte, per field and per contract address.
+//!
+//! The L1 gas is the calldata cost of EIP-2028: 4 gas per zero byte and 16 gas per nonzero byte.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::utils::bytes_to_hex;
+use super::disassemble::{disassemble, DisassembleError, Field};
+use super::{StorageTransition, ADDRESS_SIZE};
+
+/// The calldata gas of the zero byte.
+pub const CALLDATA_ZERO_BYTE_GAS: u64 = 4;
+/// The calldata gas of the nonzero byte.
+pub const CALLDATA_NONZERO_BYTE_GAS: u64 = 16;
+
+///
+/// The calldata gas of the bytes.
+///
+pub fn calldata_gas(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .map(|byte| if *byte == 0 { CALLDATA_ZERO_BYTE_GAS } else { CALLDATA_NONZERO_BYTE_GAS })
+        .sum()
+}
+
+///
+/// The raw and compressed sizes and gas of the encoded fields.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BytesStats {
+    /// The number of the fields.
+    pub count: usize,
+    pub raw_bytes: usize,
+    pub compressed_bytes: usize,
+    pub raw_gas: u64,
+    pub compressed_gas: u64,
+}
+
+impl BytesStats {
+    fn add(&mut self, raw: &[u8], compressed: &[u8]) {
+        self.count += 1;
+        self.raw_bytes += raw.len();
+        self.compressed_bytes += compressed.len();
+        self.raw_gas += calldata_gas(raw);
+        self.compressed_gas += calldata_gas(compressed);
+    }
+
+    ///
+    /// The compressed size to the raw size.
+    ///
+    pub fn ratio(&self) -> f64 {
+        self.compressed_bytes as f64 / self.raw_bytes.max(1) as f64
+    }
+
+    pub fn saved_bytes(&self) -> isize {
+        self.raw_bytes as isize - self.compressed_bytes as isize
+    }
+
+    pub fn saved_gas(&self) -> i64 {
+        self.raw_gas as i64 - self.compressed_gas as i64
+    }
+}
+
+impl fmt::Display for BytesStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} {:>10} {:>10} {:>7.2}% {:>10} {:>10}",
+            self.count,
+            self.raw_bytes,
+            self.compressed_bytes,
+            self.ratio() * 100.0,
+            self.raw_gas,
+            self.compressed_gas
+        )
+    }
+}
+
+///
+/// The compression statistics of the batch.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressionStats {
+    pub transitions: usize,
+    pub total: BytesStats,
+    pub by_type: BTreeMap<u8, BytesStats>,
+    pub by_field: BTreeMap<Field, BytesStats>,
+    pub by_address: BTreeMap<[u8; ADDRESS_SIZE], BytesStats>,
+}
+
+impl CompressionStats {
+    ///
+    /// The statistics of the compressed data of `StorageTransition::compress`.
+    ///
+    pub fn new(compressed_data: &[u8]) -> Result<Self, DisassembleError> {
+        let disassembly = disassemble(compressed_data);
+        if let Some(error) = disassembly.error {
+            return Err(error);
+        }
+
+        let mut stats = Self::default();
+        let mut address = [0u8; ADDRESS_SIZE];
+        for instruction in &disassembly.instructions {
+            if instruction.field == Field::Address {
+                address.copy_from_slice(&instruction.value);
+                stats.transitions += 1;
+            }
+
+            let raw = &instruction.value;
+            let compressed = &compressed_data[instruction.offset..instruction.offset + instruction.size];
+            stats.total.add(raw, compressed);
+            stats.by_type.entry(instruction.type_byte).or_default().add(raw, compressed);
+            stats.by_field.entry(instruction.field).or_default().add(raw, compressed);
+            stats.by_address.entry(address).or_default().add(raw, compressed);
+        }
+
+        Ok(stats)
+    }
+}
+
+impl StorageTransition {
+    ///
+    /// Compress storage transitions and collect the compression statistics.
+    ///
+    pub fn compress_with_stats(transitions: Vec<Self>) -> (Vec<u8>, CompressionStats) {
+        let compressed = Self::compress(transitions);
+        let stats = CompressionStats::new(&compressed).expect("The compressed data is always valid");
+        (compressed, stats)
+    }
+}
+
+impl fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<44} {:>8} {:>10} {:>10} {:>8} {:>10} {:>10}",
+            "", "fields", "raw bytes", "compressed", "ratio", "raw gas", "gas"
+        )?;
+        for (type_byte, stats) in &self.by_type {
+            writeln!(f, "{:<44} {}", format!("type {}", type_byte), stats)?;
+        }
+        for (field, stats) in &self.by_field {
+            writeln!(f, "{:<44} {}", field.to_string(), stats)?;
+        }
+        for (address, stats) in &self.by_address {
+            writeln!(f, "{:<44} {}", bytes_to_hex(address), stats)?;
+        }
+        write!(f, "{:<44} {}", format!("total({} transitions)", self.transitions), self.total)
+    }
+}
@@ -20,10 +20,9 @@ fn effectivity_simple_contract() {
         value: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 46, 123],
         meta: None
     }];
-    let compressed = StorageTransition::compress(transitions.clone());
-    let start_len = StorageTransition::into_bytes(transitions.clone()).len() as f64;
-    let optimise = compressed.len() as f64;
-    println!("Optimized {:.2} % for simple contract", (start_len-optimise)/start_len*100.0);
+    let (compressed, stats) = StorageTransition::compress_with_stats(transitions.clone());
+    assert_eq!(stats.total.raw_bytes, StorageTransition::into_bytes(transitions.clone()).len());
+    println!("Optimized {:.2} % for simple contract\n{}", (1.0 - stats.total.ratio()) * 100.0, stats);
     assert_eq!(transitions, StorageTransition::uncompress(compressed));
 }
 
@@ -41,9 +40,444 @@ fn effectivity_erc20() {
         value: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 222, 131, 5],
         meta: None
     }];
-    let compressed = StorageTransition::compress(transitions.clone());
-    let start_len = StorageTransition::into_bytes(transitions.clone()).len() as f64;
-    let optimise = compressed.len() as f64;
-    println!("Optimized {:.2} % for ERC20", (start_len-optimise)/start_len*100.0);
+    let (compressed, stats) = StorageTransition::compress_with_stats(transitions.clone());
+    assert_eq!(stats.total.raw_bytes, StorageTransition::into_bytes(transitions.clone()).len());
+    println!("Optimized {:.2} % for ERC20\n{}", (1.0 - stats.total.ratio()) * 100.0, stats);
     assert_eq!(transitions, StorageTransition::uncompress(compressed));
-}
\ No newline at end of file
+}
+
+#[test]
+fn correctness_leading_zeroes() {
+    for zeroes in 0..=KEY_VALUE_SIZE {
+        let mut value = [0xff; KEY_VALUE_SIZE];
+        value[..zeroes].fill(0);
+        let transitions = vec![StorageTransition {
+            address: [0xff; ADDRESS_SIZE],
+            key: value,
+            value,
+            meta: None
+        }];
+        let compressed = StorageTransition::compress(transitions.clone());
+        assert_eq!(transitions, StorageTransition::uncompress(compressed), "{} leading zeroes", zeroes);
+    }
+}
+
+#[test]
+fn canonical_encoding() {
+    use disassemble::{DisassembleError, Field};
+
+    let transitions = vec![StorageTransition {
+        address: [0xff; ADDRESS_SIZE],
+        key: [0xff; KEY_VALUE_SIZE],
+        value: [0; KEY_VALUE_SIZE],
+        meta: None
+    }];
+    let compressed = StorageTransition::compress(transitions.clone());
+    assert_eq!(Ok(transitions), StorageTransition::uncompress_canonical(&compressed));
+
+    let non_canonical = vec![
+        // The raw word with leading zero.
+        ([&[1][..], &[0xff; ADDRESS_SIZE], &[0], &[0; KEY_VALUE_SIZE], &[42]].concat(), 21, Field::Key),
+        // The leading zeroes count is not maximal.
+        ([&[1][..], &[0xff; ADDRESS_SIZE], &[41, 5], &[40, 0, 5]].concat(), 23, Field::Value),
+        // The preimage leading zeroes count is not maximal.
+        ([&[1][..], &[0xff; ADDRESS_SIZE], &[2, 41, 0, 41, 1], &[42]].concat(), 21, Field::Key),
+    ];
+    for (compressed, offset, field) in non_canonical {
+        let uncompressed = StorageTransition::uncompress(compressed.clone());
+        assert_eq!(uncompressed.len(), 1);
+        assert_eq!(StorageTransition::uncompress_canonical(&compressed), Err(DisassembleError::NonCanonical { offset, field }));
+    }
+
+    // The type 10(no leading zeroes) is not in the format, the raw word is the type 0.
+    let type_10 = [&[1][..], &[0xff; ADDRESS_SIZE], &[10], &[0xff; KEY_VALUE_SIZE], &[42]].concat();
+    assert_eq!(StorageTransition::try_uncompress(&type_10), Err(DisassembleError::UnknownType { offset: 21, type_byte: 10 }));
+}
+
+fn word(last: u8) -> [u8; KEY_VALUE_SIZE] {
+    let mut word = [0; KEY_VALUE_SIZE];
+    word[KEY_VALUE_SIZE - 1] = last;
+    word
+}
+
+fn transition(address: u8, key: u8, value: u8) -> StorageTransition {
+    let mut transition = StorageTransition { key: word(key), value: word(value), ..StorageTransition::default() };
+    transition.address[ADDRESS_SIZE - 1] = address;
+    transition
+}
+
+#[test]
+fn sparse_merkle_tree_witnesses() {
+    use tree::{KeccakTreeHasher, SparseMerkleTree, TreeHasher};
+
+    let mut tree = SparseMerkleTree::<KeccakTreeHasher>::new(16);
+    let empty_root = tree.root();
+    let transitions = vec![
+        transition(1, 1, 5),
+        transition(1, 2, 6),
+        transition(2, 1, 7),
+        transition(1, 1, 8),
+        transition(1, 2, 0),
+    ];
+
+    let mut root = tree.root();
+    let mut values = std::collections::HashMap::new();
+    for transition in &transitions {
+        let pre_value: [u8; KEY_VALUE_SIZE] = values.get(&(transition.address, transition.key)).copied().unwrap_or([0; KEY_VALUE_SIZE]);
+        let witness = tree.update(&transition.address, &transition.key, &transition.value).unwrap();
+        assert_eq!(witness.pre_value, pre_value);
+
+        let index = tree.index(&transition.address, &transition.key);
+        let leaf = |value: &[u8; KEY_VALUE_SIZE]| if value == &[0; KEY_VALUE_SIZE] {
+            KeccakTreeHasher::empty_leaf()
+        } else {
+            KeccakTreeHasher::leaf(&transition.address, &transition.key, value)
+        };
+        assert_eq!(witness.root::<KeccakTreeHasher>(&index, leaf(&witness.pre_value)), root);
+        root = witness.root::<KeccakTreeHasher>(&index, leaf(&transition.value));
+        assert_eq!(tree.root(), root);
+
+        values.insert((transition.address, transition.key), transition.value);
+        assert_eq!(tree.get(&transition.address, &transition.key), transition.value);
+    }
+
+    let mut other = SparseMerkleTree::<KeccakTreeHasher>::new(16);
+    other.apply(&[transition(2, 1, 7), transition(1, 1, 8)]).unwrap();
+    assert_eq!(tree.root(), other.root(), "The root depends only on the final values");
+
+    tree.apply(&[transition(1, 1, 0), transition(2, 1, 0)]).unwrap();
+    assert_eq!(tree.root(), empty_root, "The deleted slots are empty");
+}
+
+#[test]
+fn sparse_merkle_tree_collision() {
+    use tree::{KeccakTreeHasher, SparseMerkleTree, TreeError};
+
+    let mut tree = SparseMerkleTree::<KeccakTreeHasher>::new(1);
+    let result = tree.apply(&[transition(1, 1, 1), transition(1, 2, 1), transition(1, 3, 1)]);
+    assert!(matches!(result, Err(TreeError::IndexCollision { .. })));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn prestate_diff_transitions() {
+    let block = r#"[
+        {
+            "txHash": "0x01",
+            "result": {
+                "pre": {
+                    "0x00000000000000000000000000000000000000aa": {
+                        "balance": "0x10",
+                        "storage": {
+                            "0x0000000000000000000000000000000000000000000000000000000000000002": "0x0000000000000000000000000000000000000000000000000000000000000007",
+                            "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000005"
+                        }
+                    },
+                    "0x00000000000000000000000000000000000000bb": {"balance": "0x1"}
+                },
+                "post": {
+                    "0x00000000000000000000000000000000000000aa": {
+                        "storage": {
+                            "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000006",
+                            "0x0000000000000000000000000000000000000000000000000000000000000003": "0x0000000000000000000000000000000000000000000000000000000000000009"
+                        }
+                    },
+                    "0x00000000000000000000000000000000000000bb": {"balance": "0x2"}
+                }
+            }
+        },
+        {
+            "txHash": "0x02",
+            "result": {
+                "pre": {"0x00000000000000000000000000000000000000cc": {"storage": {"0x05": "0x01"}}},
+                "post": {}
+            }
+        }
+    ]"#;
+
+    let expected = vec![
+        transition(0xaa, 1, 6),
+        // The slot only in `pre` is deleted.
+        transition(0xaa, 2, 0),
+        // The slot only in `post` is created.
+        transition(0xaa, 3, 9),
+        // The selfdestructed account storage is cleared.
+        transition(0xcc, 5, 0),
+    ];
+    let transitions = StorageTransition::from_prestate_diff(block).unwrap();
+    assert_eq!(transitions, expected);
+
+    let response = format!(r#"{{"jsonrpc": "2.0", "id": 1, "result": {}}}"#, block);
+    assert_eq!(StorageTransition::from_prestate_diff(&response).unwrap(), expected);
+    assert_eq!(StorageTransition::uncompress(StorageTransition::compress(transitions.clone())), transitions);
+
+    assert!(matches!(StorageTransition::from_prestate_diff("{"), Err(JsonError::Json(_))));
+    assert!(matches!(
+        StorageTransition::from_prestate_diff(r#"{"pre": {"0xzz": {}}}"#),
+        Err(JsonError::InvalidFormat(_))
+    ));
+    assert!(matches!(
+        StorageTransition::from_prestate_diff(r#"{"pre": {"0x": {}}}"#),
+        Err(JsonError::InvalidFormat(_))
+    ));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn storage_layout_meta() {
+    use layout::{StorageLayout, StorageLayouts};
+    use sha3::Digest;
+
+    // uint256 total; uint256[] values; bytes data; mapping(address => uint256[]) lists; struct { uint256 a; uint256[] b; } s;
+    let layout = r#"{
+        "storage": [
+            {"astId": 3, "contract": "Sample.sol:Sample", "label": "total", "offset": 0, "slot": "0", "type": "t_uint256"},
+            {"astId": 6, "contract": "Sample.sol:Sample", "label": "values", "offset": 0, "slot": "1", "type": "t_array(t_uint256)dyn_storage"},
+            {"astId": 8, "contract": "Sample.sol:Sample", "label": "data", "offset": 0, "slot": "2", "type": "t_bytes_storage"},
+            {"astId": 13, "contract": "Sample.sol:Sample", "label": "lists", "offset": 0, "slot": "3", "type": "t_mapping(t_address,t_array(t_uint256)dyn_storage)"},
+            {"astId": 20, "contract": "Sample.sol:Sample", "label": "s", "offset": 0, "slot": "4", "type": "t_struct(S)18_storage"}
+        ],
+        "types": {
+            "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+            "t_array(t_uint256)dyn_storage": {"base": "t_uint256", "encoding": "dynamic_array", "label": "uint256[]", "numberOfBytes": "32"},
+            "t_bytes_storage": {"encoding": "bytes", "label": "bytes", "numberOfBytes": "32"},
+            "t_mapping(t_address,t_array(t_uint256)dyn_storage)": {"encoding": "mapping", "key": "t_address", "label": "mapping(address => uint256[])", "numberOfBytes": "32", "value": "t_array(t_uint256)dyn_storage"},
+            "t_struct(S)18_storage": {"encoding": "inplace", "label": "struct Sample.S", "numberOfBytes": "64", "members": [
+                {"astId": 15, "contract": "Sample.sol:Sample", "label": "a", "offset": 0, "slot": "0", "type": "t_uint256"},
+                {"astId": 17, "contract": "Sample.sol:Sample", "label": "b", "offset": 0, "slot": "1", "type": "t_array(t_uint256)dyn_storage"}
+            ]},
+            "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"}
+        }
+    }"#;
+    let layout = StorageLayout::from_json(layout).unwrap();
+
+    let word = |value: u8| {
+        let mut word = [0u8; KEY_VALUE_SIZE];
+        word[KEY_VALUE_SIZE - 1] = value;
+        word
+    };
+    let holder = bytes_from_hex::<KEY_VALUE_SIZE>("0x00000000000000000000000000000000000000aa").unwrap();
+    let list_slot: [u8; KEY_VALUE_SIZE] = sha3::Keccak256::digest(&[holder, word(3)].concat()).into();
+
+    assert_eq!(layout.infer_meta(&word(0), &[]), None);
+    for (preimage, offset) in [(word(1), word(7)), (word(2), word(0)), (word(5), word(1))] {
+        let key = slot_from_preimage_and_offset(preimage, offset);
+        assert_eq!(layout.infer_meta(&key, &[]), Some((preimage, offset)));
+    }
+    let key = slot_from_preimage_and_offset(list_slot, word(2));
+    assert_eq!(layout.infer_meta(&key, &[]), None);
+    assert_eq!(layout.infer_meta(&key, &[word(1), holder]), Some((list_slot, word(2))));
+
+    let address = [0x11; ADDRESS_SIZE];
+    let mut layouts = StorageLayouts::new();
+    layouts.insert(address, layout);
+    let mut transitions = vec![
+        StorageTransition { address, key: slot_from_preimage_and_offset(word(1), word(7)), value: word(9), meta: None },
+        StorageTransition { address: [0x22; ADDRESS_SIZE], key: slot_from_preimage_and_offset(word(1), word(7)), value: word(9), meta: None },
+    ];
+    let plain = StorageTransition::compress(transitions.clone());
+    layouts.annotate(&mut transitions, &[]);
+    assert_eq!(transitions[0].meta, Some((word(1), word(7))));
+    assert_eq!(transitions[1].meta, None, "The unknown contract");
+
+    let compressed = StorageTransition::compress(transitions.clone());
+    assert!(compressed.len() < plain.len());
+    for transition in transitions.iter_mut() {
+        transition.meta = None;
+    }
+    assert_eq!(StorageTransition::uncompress(compressed), transitions);
+
+    assert!(matches!(StorageLayout::from_json(r#"{"storage": [{"slot": "x", "type": "t_uint256"}]}"#), Err(JsonError::InvalidFormat(_))));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_json_lines() {
+    let mut transitions = vec![transition(1, 2, 3), transition(4, 5, 0)];
+    transitions[1].meta = Some(([7; KEY_VALUE_SIZE], [0; KEY_VALUE_SIZE]));
+
+    let json = serde_json::to_value(&transitions[0]).unwrap();
+    assert_eq!(json["address"], format!("0x{}01", "00".repeat(ADDRESS_SIZE - 1)));
+    assert!(json.get("meta").is_none());
+    let json = serde_json::to_value(&transitions[1]).unwrap();
+    assert_eq!(json["meta"]["preimage"], format!("0x{}", "07".repeat(KEY_VALUE_SIZE)));
+
+    let lines = StorageTransition::to_json_lines(&transitions);
+    assert_eq!(lines.lines().count(), 2);
+    assert_eq!(StorageTransition::from_json_lines(&lines).unwrap(), transitions);
+
+    // The short hex, the meta pair and the empty lines.
+    let hand_written = r#"
+        {"address": "0x01", "key": "0x02", "value": "0x03", "meta": null}
+        {"address": "0x04", "key": "0x05", "value": "0x0", "meta": ["0x0707070707070707070707070707070707070707070707070707070707070707", "0x00"]}
+    "#;
+    assert_eq!(StorageTransition::from_json_lines(hand_written).unwrap(), transitions);
+
+    let error = StorageTransition::from_json_lines("{\"address\": \"0x01\", \"key\": \"0x02\", \"value\": \"0x03\"}\n{\"address\": \"0x01\"}").unwrap_err();
+    assert_eq!(error.line, 2);
+    assert!(serde_json::from_str::<StorageTransition>(&format!(r#"{{"address": "0x{}", "key": "0x", "value": "0x"}}"#, "00".repeat(ADDRESS_SIZE + 1))).is_err());
+}
+
+#[test]
+fn records_from_bytes() {
+    let transitions = vec![transition(1, 2, 3), transition(4, 5, 6)];
+    let data = StorageTransition::into_bytes(transitions.clone());
+    assert_eq!(StorageTransition::from_bytes(&data).unwrap(), transitions);
+    assert_eq!(StorageTransition::from_bytes(&[]).unwrap(), vec![]);
+
+    let mut records = StorageTransition::iter_bytes(&data).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records.next(), Some(transitions[0].clone()));
+    assert_eq!(records.len(), 1);
+
+    let error = StorageTransition::from_bytes(&data[..RECORD_SIZE + 1]).unwrap_err();
+    assert_eq!(error, RecordsLengthError { length: RECORD_SIZE + 1 });
+    assert!(StorageTransition::iter_bytes(&data[1..]).is_err());
+}
+
+#[test]
+fn disassemble_listing() {
+    use disassemble::{DisassembleError, Encoding, Field};
+
+    let mut transitions = vec![transition(1, 2, 3), transition(4, 5, 0)];
+    transitions[0].key = [0xff; KEY_VALUE_SIZE];
+    transitions[1].key = slot_from_preimage_and_offset(word(1), word(7));
+    transitions[1].meta = Some((word(1), word(7)));
+    let compressed = StorageTransition::compress(transitions.clone());
+
+    let disassembly = disassemble(&compressed);
+    assert_eq!(disassembly.error, None);
+    let encodings: Vec<_> = disassembly.instructions.iter().map(|instruction| (instruction.field, instruction.encoding)).collect();
+    assert_eq!(encodings, vec![
+        (Field::Address, Encoding::Address),
+        (Field::Key, Encoding::Raw),
+        (Field::Value, Encoding::LeadingZeroes(31)),
+        (Field::Address, Encoding::Address),
+        (Field::Key, Encoding::PreimageOffset { preimage: transitions[1].meta.unwrap().0, offset: transitions[1].meta.unwrap().1 }),
+        (Field::Value, Encoding::LeadingZeroes(32)),
+    ]);
+    assert_eq!(disassembly.instructions[4].value, transitions[1].key.to_vec());
+    assert_eq!(disassembly.instructions[1].saved(), -1);
+    assert_eq!(disassembly.saved(), (StorageTransition::into_bytes(transitions).len() - compressed.len()) as isize);
+    assert_eq!(disassembly.instructions.iter().map(|instruction| instruction.size).sum::<usize>(), compressed.len());
+
+    let failure = |data: &[u8]| disassemble(data).error;
+    assert_eq!(failure(&compressed[..compressed.len() - 1]), Some(DisassembleError::IncompleteRecord { record: 1 }));
+    assert_eq!(failure(&compressed[..5]), Some(DisassembleError::Truncated { offset: 0, field: Field::Address }));
+    assert_eq!(failure(&[1; 21]), Some(DisassembleError::IncompleteRecord { record: 0 }));
+    let mut corrupted = compressed.clone();
+    corrupted[21] = 3;
+    let dictionary_ref = disassemble(&corrupted);
+    assert_eq!(dictionary_ref.error, Some(DisassembleError::UnsupportedType { offset: 21, type_byte: 3 }));
+    assert_eq!(dictionary_ref.instructions[1].encoding, Encoding::DictionaryRef);
+    assert_eq!(dictionary_ref.instructions.len(), 2);
+    corrupted[21] = 10;
+    assert_eq!(failure(&corrupted), Some(DisassembleError::UnknownType { offset: 21, type_byte: 10 }));
+    corrupted[21] = 43;
+    assert_eq!(failure(&corrupted), Some(DisassembleError::UnknownType { offset: 21, type_byte: 43 }));
+    corrupted[21] = 1;
+    let partial = disassemble(&corrupted);
+    assert_eq!(partial.instructions.len(), 1);
+    assert_eq!(partial.error, Some(DisassembleError::UnexpectedType { offset: 21, field: Field::Key, type_byte: 1 }));
+}
+
+#[test]
+fn try_uncompress() {
+    use disassemble::DisassembleError;
+
+    let transitions = WorkloadGenerator::new(5).batch(WorkloadMix::default(), 20);
+    let compressed = StorageTransition::compress(transitions);
+    assert_eq!(StorageTransition::try_uncompress(&compressed), Ok(StorageTransition::uncompress(compressed.clone())));
+    assert_eq!(StorageTransition::try_uncompress(&[]), Ok(Vec::new()));
+
+    assert_eq!(
+        StorageTransition::try_uncompress(&compressed[..compressed.len() - 1]).map(|_| ()),
+        Err(disassemble(&compressed[..compressed.len() - 1]).error.unwrap())
+    );
+    assert_eq!(StorageTransition::try_uncompress(&[2]), Err(DisassembleError::UnexpectedType { offset: 0, field: disassemble::Field::Address, type_byte: 2 }));
+}
+
+#[test]
+fn compression_stats() {
+    use disassemble::Field;
+
+    let mut transitions = vec![transition(1, 2, 3), transition(1, 5, 0), transition(4, 5, 6)];
+    transitions[2].value = [0xff; KEY_VALUE_SIZE];
+    let (compressed, stats) = StorageTransition::compress_with_stats(transitions.clone());
+
+    assert_eq!(stats.transitions, 3);
+    assert_eq!(stats.total.count, 9);
+    assert_eq!(stats.total.raw_bytes, 3 * RECORD_SIZE);
+    assert_eq!(stats.total.compressed_bytes, compressed.len());
+    assert_eq!(stats.total.compressed_gas, stats::calldata_gas(&compressed));
+    assert_eq!(stats.total.raw_gas, stats::calldata_gas(&StorageTransition::into_bytes(transitions.clone())));
+
+    assert_eq!(stats.by_type[&1].count, 3);
+    assert_eq!(stats.by_type[&0].count, 1);
+    assert_eq!(stats.by_type[&0].saved_bytes(), -1);
+    assert_eq!(stats.by_type[&41].count, 4);
+    assert_eq!(stats.by_type[&42].count, 1);
+    assert_eq!(stats.by_field[&Field::Address].raw_bytes, 3 * ADDRESS_SIZE);
+    assert_eq!(stats.by_field[&Field::Value].compressed_bytes, 2 + 1 + 1 + KEY_VALUE_SIZE);
+    assert_eq!(stats.by_address[&transitions[0].address].count, 6);
+    assert_eq!(stats.by_address[&transitions[2].address].count, 3);
+    assert_eq!(
+        stats.by_address.values().map(|stats| stats.compressed_bytes).sum::<usize>(),
+        compressed.len()
+    );
+    assert!(stats.total.ratio() < 1.0 && stats.total.saved_gas() > 0);
+
+    assert!(CompressionStats::new(&compressed[1..]).is_err());
+}
+
+#[test]
+fn synthetic_workload() {
+    let batch = |seed| WorkloadGenerator::new(seed).batch(WorkloadMix::default(), 200);
+    let transitions = batch(7);
+    assert_eq!(transitions, batch(7));
+    assert_ne!(transitions, batch(8));
+
+    let annotated = transitions.iter().filter(|transition| transition.meta.is_some()).count();
+    assert!(annotated > 0 && annotated < transitions.len());
+    for transition in &transitions {
+        if let Some((preimage, offset)) = transition.meta {
+            assert_eq!(slot_from_preimage_and_offset(preimage, offset), transition.key);
+        }
+    }
+
+    // The ERC20 balance is `_balances[holder]` at slot 0.
+    let mut generator = WorkloadGenerator::new(1);
+    let transfer = generator.erc20_transfer();
+    assert_eq!(transfer.len(), 2);
+    assert!(transfer.iter().all(|transition| transition.address == transfer[0].address));
+
+    // The NFT mint pushes the token id to `_allTokens` at slot 8.
+    let mut generator = WorkloadGenerator::with_size(1, workload::WorkloadSize { collections: 1, ..Default::default() });
+    generator.nft_mint();
+    let mint = generator.nft_mint();
+    let mut length = [0u8; KEY_VALUE_SIZE];
+    length[KEY_VALUE_SIZE - 1] = 8;
+    let array = mint.iter().find(|transition| transition.key == length).expect("The array length is written");
+    assert_eq!(array.value[KEY_VALUE_SIZE - 1], 2);
+    let element = mint.iter().find(|transition| transition.meta.is_some()).expect("The array element is written");
+    assert_eq!(element.meta.unwrap().0, length);
+    assert_eq!(element.value[KEY_VALUE_SIZE - 1], 1);
+
+    // The EIP-1967 admin slot.
+    let admin = "b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+    let implementation = "360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+    let mut generator = WorkloadGenerator::new(3);
+    for _ in 0..10 {
+        let key: String = generator.proxy_admin_write()[0].key.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert!(key == admin || key == implementation);
+    }
+
+    let compressed = StorageTransition::compress(transitions.clone());
+    let without_meta: Vec<_> = transitions.iter().map(|transition| StorageTransition { meta: None, ..*transition }).collect();
+    assert_eq!(StorageTransition::uncompress(compressed.clone()), without_meta);
+    assert!(compressed.len() < StorageTransition::compress(without_meta).len());
+
+    let (_, stats) = StorageTransition::compress_with_stats(transitions);
+    println!("{}", stats);
+    assert!(stats.total.ratio() < 1.0);
+}
@@ -0,0 +1,239 @@
+//!
+//! The sparse merkle tree of the storage slots `(address, key) -> value`.
+//!
+//! The tree is generic over the hasher, the circuit friendly hashers live with the circuits.
+//! The zero value slot is the empty leaf, so the deleted slot is the same as never written one.
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+
+use sha3::Digest;
+
+use super::utils::bytes_to_hex;
+use super::{StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE};
+
+///
+/// The hashes of the tree leaves and nodes.
+///
+pub trait TreeHasher {
+    type Hash: Copy + PartialEq + fmt::Debug;
+
+    /// The leaf of the zero value slot.
+    fn empty_leaf() -> Self::Hash;
+
+    /// The leaf of the nonzero value slot.
+    fn leaf(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], value: &[u8; KEY_VALUE_SIZE]) -> Self::Hash;
+
+    fn node(left: &Self::Hash, right: &Self::Hash) -> Self::Hash;
+
+    /// The slot leaf index bits, the least significant first, at least `depth` of them.
+    fn index(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], depth: usize) -> Vec<bool>;
+}
+
+///
+/// The keccak256 hasher, the leaf index is the low bits of `keccak(address || key)`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeccakTreeHasher;
+
+impl TreeHasher for KeccakTreeHasher {
+    type Hash = [u8; 32];
+
+    fn empty_leaf() -> Self::Hash {
+        [0; 32]
+    }
+
+    fn leaf(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], value: &[u8; KEY_VALUE_SIZE]) -> Self::Hash {
+        let mut hasher = sha3::Keccak256::new();
+        hasher.update(address);
+        hasher.update(key);
+        hasher.update(value);
+        hasher.finalize().into()
+    }
+
+    fn node(left: &Self::Hash, right: &Self::Hash) -> Self::Hash {
+        let mut hasher = sha3::Keccak256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+
+    fn index(address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE], depth: usize) -> Vec<bool> {
+        assert!(depth <= 256, "The keccak tree depth is at most 256");
+        let mut hasher = sha3::Keccak256::new();
+        hasher.update(address);
+        hasher.update(key);
+        let hash = hasher.finalize();
+        (0..depth).map(|bit| hash[31 - bit / 8] >> (bit % 8) & 1 == 1).collect()
+    }
+}
+
+///
+/// The slot update witness: the value before the update and the siblings from the leaf level up.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleWitness<H> {
+    pub pre_value: [u8; KEY_VALUE_SIZE],
+    pub path: Vec<H>,
+}
+
+impl<H: Copy> MerkleWitness<H> {
+    ///
+    /// The root of the tree with the `leaf` at the `index`.
+    ///
+    pub fn root<T: TreeHasher<Hash = H>>(&self, index: &[bool], leaf: H) -> H {
+        self.path.iter().zip(index).fold(leaf, |current, (sibling, is_right)| {
+            if *is_right {
+                T::node(sibling, &current)
+            } else {
+                T::node(&current, sibling)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError {
+    /// The slot has the same leaf index as the other nonzero slot, the tree is too shallow.
+    IndexCollision {
+        address: [u8; ADDRESS_SIZE],
+        key: [u8; KEY_VALUE_SIZE],
+    },
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::IndexCollision { address, key } => {
+                let slot = bytes_to_hex(&[&address[..], &key[..]].concat());
+                write!(f, "The slot {} collides with the other slot leaf index", slot)
+            },
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+type Slot = ([u8; ADDRESS_SIZE], [u8; KEY_VALUE_SIZE], [u8; KEY_VALUE_SIZE]);
+
+///
+/// The sparse merkle tree, only the nonempty nodes are stored.
+///
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree<H: TreeHasher> {
+    depth: usize,
+    /// The roots of the empty subtrees by the height.
+    empty: Vec<H::Hash>,
+    /// The nodes by the level and the index bits above the level.
+    nodes: HashMap<(usize, Vec<bool>), H::Hash>,
+    /// The nonzero slots by the leaf index.
+    slots: HashMap<Vec<bool>, Slot>,
+}
+
+impl<H: TreeHasher> SparseMerkleTree<H> {
+    ///
+    /// The empty tree of the `depth` levels above the leaves.
+    ///
+    pub fn new(depth: usize) -> Self {
+        let mut empty = Vec::with_capacity(depth + 1);
+        empty.push(H::empty_leaf());
+        for level in 0..depth {
+            let node = H::node(&empty[level], &empty[level]);
+            empty.push(node);
+        }
+
+        Self {
+            depth,
+            empty,
+            nodes: HashMap::new(),
+            slots: HashMap::new(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn root(&self) -> H::Hash {
+        self.node(self.depth, &[])
+    }
+
+    ///
+    /// The leaf index bits of the slot, the least significant first.
+    ///
+    pub fn index(&self, address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE]) -> Vec<bool> {
+        let mut index = H::index(address, key, self.depth);
+        index.truncate(self.depth);
+        index
+    }
+
+    ///
+    /// The slot value, zero if the slot is empty.
+    ///
+    pub fn get(&self, address: &[u8; ADDRESS_SIZE], key: &[u8; KEY_VALUE_SIZE]) -> [u8; KEY_VALUE_SIZE] {
+        match self.slots.get(&self.index(address, key)) {
+            Some((slot_address, slot_key, value)) if slot_address == address && slot_key == key => *value,
+            _ => [0; KEY_VALUE_SIZE],
+        }
+    }
+
+    ///
+    /// Set the slot value and return the witness of the update.
+    ///
+    pub fn update(
+        &mut self,
+        address: &[u8; ADDRESS_SIZE],
+        key: &[u8; KEY_VALUE_SIZE],
+        value: &[u8; KEY_VALUE_SIZE],
+    ) -> Result<MerkleWitness<H::Hash>, TreeError> {
+        let index = self.index(address, key);
+        if let Some((slot_address, slot_key, _)) = self.slots.get(&index) {
+            if slot_address != address || slot_key != key {
+                return Err(TreeError::IndexCollision { address: *address, key: *key });
+            }
+        }
+
+        let pre_value = self.get(address, key);
+        let path: Vec<H::Hash> = (0..self.depth)
+            .map(|level| {
+                let mut sibling = index[level..].to_vec();
+                sibling[0] = !sibling[0];
+                self.nodes.get(&(level, sibling)).copied().unwrap_or(self.empty[level])
+            })
+            .collect();
+
+        let mut current = if value == &[0; KEY_VALUE_SIZE] {
+            self.slots.remove(&index);
+            H::empty_leaf()
+        } else {
+            self.slots.insert(index.clone(), (*address, *key, *value));
+            H::leaf(address, key, value)
+        };
+        for level in 0..self.depth {
+            self.nodes.insert((level, index[level..].to_vec()), current);
+            current = if index[level] {
+                H::node(&path[level], &current)
+            } else {
+                H::node(&current, &path[level])
+            };
+        }
+        self.nodes.insert((self.depth, Vec::new()), current);
+
+        Ok(MerkleWitness { pre_value, path })
+    }
+
+    ///
+    /// Apply the transitions in order and return the witness of every update.
+    ///
+    pub fn apply(&mut self, transitions: &[StorageTransition]) -> Result<Vec<MerkleWitness<H::Hash>>, TreeError> {
+        transitions
+            .iter()
+            .map(|transition| self.update(&transition.address, &transition.key, &transition.value))
+            .collect()
+    }
+
+    fn node(&self, level: usize, index: &[bool]) -> H::Hash {
+        self.nodes.get(&(level, index.to_vec())).copied().unwrap_or(self.empty[level])
+    }
+}
@@ -6,6 +6,45 @@ use super::KEY_VALUE_SIZE;
 
 use sha3::Digest;
 
+///
+/// The error of the JSON importers: the JSON itself or its format.
+///
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub enum JsonError {
+    /// The file isn't JSON.
+    Json(serde_json::Error),
+    /// The JSON isn't the expected document, e.g. the prestate diff or the storage layout.
+    InvalidFormat(String),
+}
+
+#[cfg(feature = "json")]
+impl JsonError {
+    pub(crate) fn invalid(message: &str) -> Self {
+        JsonError::InvalidFormat(message.to_owned())
+    }
+}
+
+#[cfg(feature = "json")]
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Json(error) => write!(f, "json error: {}", error),
+            JsonError::InvalidFormat(message) => write!(f, "invalid format: {}", message),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl std::error::Error for JsonError {}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for JsonError {
+    fn from(error: serde_json::Error) -> Self {
+        JsonError::Json(error)
+    }
+}
+
 ///
 /// Compress the value using first zeroes.
 ///
@@ -26,10 +65,9 @@ pub fn compress_leading_zeroes(value: [u8; KEY_VALUE_SIZE]) -> Vec<u8> {
 /// Uncompress the value with first zeroes.
 ///
 pub fn uncompress_leading_zeroes(slice: &[u8]) -> ([u8; KEY_VALUE_SIZE], u8) {
+    assert!(slice[0] >= 11 && slice[0] as usize - 10 <= KEY_VALUE_SIZE, "Invalid leading zeroes type");
     let zero_bytes = slice[0] - 10;
 
-    assert!(zero_bytes > 10 && zero_bytes as usize <= KEY_VALUE_SIZE);
-
     let mut result = [0u8; KEY_VALUE_SIZE];
     for index in zero_bytes as usize..KEY_VALUE_SIZE {
         result[index] = slice[index-zero_bytes as usize + 1];
@@ -39,6 +77,13 @@ pub fn uncompress_leading_zeroes(slice: &[u8]) -> ([u8; KEY_VALUE_SIZE], u8) {
 }
 
 pub fn slot_from_preimage_and_offset(preimage: [u8; KEY_VALUE_SIZE], offset: [u8; KEY_VALUE_SIZE]) -> [u8; KEY_VALUE_SIZE] {
+    checked_slot_from_preimage_and_offset(preimage, offset).expect("The slot overflows")
+}
+
+///
+/// `keccak256(preimage) + offset`, `None` on the overflow.
+///
+pub fn checked_slot_from_preimage_and_offset(preimage: [u8; KEY_VALUE_SIZE], offset: [u8; KEY_VALUE_SIZE]) -> Option<[u8; KEY_VALUE_SIZE]> {
     let image = sha3::Keccak256::digest(
         preimage.as_slice()
     ).as_slice().to_vec();
@@ -58,6 +103,47 @@ pub fn slot_from_preimage_and_offset(preimage: [u8; KEY_VALUE_SIZE], offset: [u8
             ptr -= 1;
         }
     }
-    assert_eq!(add, 0);
+    (add == 0).then_some(result)
+}
+
+///
+/// The keccak256 of the concatenated words, e.g. the mapping slot `keccak(key . slot)`.
+///
+pub fn keccak(parts: &[&[u8; KEY_VALUE_SIZE]]) -> [u8; KEY_VALUE_SIZE] {
+    let mut hasher = sha3::Keccak256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+///
+/// Parse the 0x-prefixed hex string of at least one digit into `N` bytes, the shorter strings are padded
+/// with the leading zeroes.
+///
+#[cfg(feature = "json")]
+pub fn bytes_from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let digits = hex.strip_prefix("0x").or_else(|| hex.strip_prefix("0X"))?.as_bytes();
+    if digits.is_empty() || digits.len() > 2 * N {
+        return None;
+    }
+
+    let mut result = [0u8; N];
+    for (position, digit) in digits.iter().rev().enumerate() {
+        let nibble = (*digit as char).to_digit(16)? as u8;
+        result[N - 1 - position / 2] |= nibble << (4 * (position % 2));
+    }
+    Some(result)
+}
+
+///
+/// The 0x-prefixed lowercase hex string of the bytes.
+///
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(2 + 2 * bytes.len());
+    result.push_str("0x");
+    for byte in bytes {
+        result.push_str(&format!("{:02x}", byte));
+    }
     result
-}
\ No newline at end of file
+}
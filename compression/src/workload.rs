@@ -0,0 +1,416 @@
+//!
+//! The deterministic synthetic workloads: the storage diffs of the popular contracts with the
+//! storage layouts of the real implementations.
+//!
+//! - ERC20 transfers(OpenZeppelin): `_balances` mapping at slot 0.
+//! - Uniswap V2 swaps: the token balances of the pair and the trader, the packed
+//!   `reserve0 | reserve1 << 112 | blockTimestampLast << 224` at slot 8 and the cumulative prices at slots 9 and 10.
+//! - NFT mints(OpenZeppelin ERC721Enumerable): `_owners` 2, `_balances` 3, `_ownedTokens` 6,
+//!   `_ownedTokensIndex` 7, `_allTokens` array 8 and `_allTokensIndex` 9.
+//! - Proxy admin writes: the EIP-1967 admin and implementation slots.
+//!
+//! The mapping slots are `keccak256(key . slot)` without meta, the dynamic array elements are
+//! `keccak256(slot) + index` with the meta. The same seed always gives the same transitions.
+//!
+
+use std::collections::HashMap;
+
+use sha3::Digest;
+
+use super::utils::{keccak, slot_from_preimage_and_offset};
+use super::{StorageTransition, ADDRESS_SIZE, KEY_VALUE_SIZE};
+
+type Address = [u8; ADDRESS_SIZE];
+type Word = [u8; KEY_VALUE_SIZE];
+
+const ERC20_BALANCES_SLOT: u64 = 0;
+const PAIR_RESERVES_SLOT: u64 = 8;
+const PAIR_PRICE0_CUMULATIVE_SLOT: u64 = 9;
+const PAIR_PRICE1_CUMULATIVE_SLOT: u64 = 10;
+const NFT_OWNERS_SLOT: u64 = 2;
+const NFT_BALANCES_SLOT: u64 = 3;
+const NFT_OWNED_TOKENS_SLOT: u64 = 6;
+const NFT_OWNED_TOKENS_INDEX_SLOT: u64 = 7;
+const NFT_ALL_TOKENS_SLOT: u64 = 8;
+const NFT_ALL_TOKENS_INDEX_SLOT: u64 = 9;
+
+/// The seconds between the blocks.
+const BLOCK_TIME: u64 = 12;
+
+///
+/// The relative weights of the transactions kinds in the batch.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkloadMix {
+    pub erc20_transfers: u32,
+    pub uniswap_swaps: u32,
+    pub nft_mints: u32,
+    pub proxy_admin_writes: u32,
+}
+
+impl Default for WorkloadMix {
+    fn default() -> Self {
+        Self {
+            erc20_transfers: 60,
+            uniswap_swaps: 25,
+            nft_mints: 12,
+            proxy_admin_writes: 3,
+        }
+    }
+}
+
+///
+/// The population sizes, the popular contracts and accounts are picked more often.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkloadSize {
+    pub accounts: usize,
+    pub tokens: usize,
+    pub pools: usize,
+    pub collections: usize,
+    pub proxies: usize,
+}
+
+impl Default for WorkloadSize {
+    fn default() -> Self {
+        Self {
+            accounts: 1000,
+            tokens: 20,
+            pools: 10,
+            collections: 5,
+            proxies: 5,
+        }
+    }
+}
+
+struct Pool {
+    address: Address,
+    token0: usize,
+    token1: usize,
+    reserve0: u128,
+    reserve1: u128,
+    price0_cumulative: u128,
+    price1_cumulative: u128,
+}
+
+#[derive(Default)]
+struct Collection {
+    total_supply: u64,
+    balances: HashMap<usize, u64>,
+}
+
+///
+/// The seedable generator of the storage transitions batches.
+///
+pub struct WorkloadGenerator {
+    rng: SplitMix64,
+    timestamp: u64,
+    accounts: Vec<Address>,
+    tokens: Vec<Address>,
+    pools: Vec<Pool>,
+    collections: Vec<(Address, Collection)>,
+    proxies: Vec<Address>,
+    /// The token balances by the token and the holder.
+    balances: HashMap<(usize, Address), u128>,
+}
+
+impl WorkloadGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self::with_size(seed, WorkloadSize::default())
+    }
+
+    pub fn with_size(seed: u64, size: WorkloadSize) -> Self {
+        assert!(size.accounts > 1 && size.tokens > 1, "The transfers and swaps need two accounts and two tokens");
+        let address = |kind: &str, index: usize| -> Address {
+            let mut hasher = sha3::Keccak256::new();
+            hasher.update(seed.to_be_bytes());
+            hasher.update(kind.as_bytes());
+            hasher.update((index as u64).to_be_bytes());
+            let hash = hasher.finalize();
+            let mut address = [0u8; ADDRESS_SIZE];
+            address.copy_from_slice(&hash[KEY_VALUE_SIZE - ADDRESS_SIZE..]);
+            address
+        };
+
+        let mut rng = SplitMix64(seed);
+        let pools = (0..size.pools)
+            .map(|index| {
+                let token0 = rng.below(size.tokens);
+                let token1 = (token0 + 1 + rng.below(size.tokens - 1)) % size.tokens;
+                Pool {
+                    address: address("pool", index),
+                    token0,
+                    token1,
+                    reserve0: rng.between(1 << 70, 1 << 90),
+                    reserve1: rng.between(1 << 70, 1 << 90),
+                    price0_cumulative: rng.next() as u128 * rng.next() as u128,
+                    price1_cumulative: rng.next() as u128 * rng.next() as u128,
+                }
+            })
+            .collect();
+
+        Self {
+            timestamp: 1_700_000_000 + rng.below(1_000_000) as u64,
+            accounts: (0..size.accounts).map(|index| address("account", index)).collect(),
+            tokens: (0..size.tokens).map(|index| address("token", index)).collect(),
+            pools,
+            collections: (0..size.collections).map(|index| (address("collection", index), Collection::default())).collect(),
+            proxies: (0..size.proxies).map(|index| address("proxy", index)).collect(),
+            balances: HashMap::new(),
+            rng,
+        }
+    }
+
+    ///
+    /// The transitions of the block of `transactions` transactions, picked by the mix weights.
+    ///
+    pub fn batch(&mut self, mix: WorkloadMix, transactions: usize) -> Vec<StorageTransition> {
+        let weights = [mix.erc20_transfers, mix.uniswap_swaps, mix.nft_mints, mix.proxy_admin_writes];
+        let total: u32 = weights.iter().sum();
+        assert!(total > 0, "The mix has no transactions");
+
+        self.timestamp += BLOCK_TIME;
+        let mut transitions = Vec::new();
+        for _ in 0..transactions {
+            let mut pick = self.rng.below(total as usize) as u32;
+            let kind = weights.iter().position(|weight| {
+                let found = pick < *weight;
+                pick = pick.saturating_sub(*weight);
+                found
+            });
+            transitions.extend(match kind {
+                Some(0) => self.erc20_transfer(),
+                Some(1) => self.uniswap_swap(),
+                Some(2) => self.nft_mint(),
+                _ => self.proxy_admin_write(),
+            });
+        }
+        transitions
+    }
+
+    ///
+    /// The sender and recipient balances of the token transfer.
+    ///
+    pub fn erc20_transfer(&mut self) -> Vec<StorageTransition> {
+        let token = self.rng.popular(self.tokens.len());
+        let from_index = self.rng.popular(self.accounts.len());
+        let mut to_index = self.rng.popular(self.accounts.len());
+        if to_index == from_index {
+            to_index = (from_index + 1 + self.rng.below(self.accounts.len() - 1)) % self.accounts.len();
+        }
+        let (from, to) = (self.accounts[from_index], self.accounts[to_index]);
+
+        let from_balance = self.balance(token, from);
+        // The whole balance is sent sometimes, the slot is deleted then.
+        let amount = if self.rng.below(10) == 0 { from_balance } else { self.rng.between(0, from_balance) };
+        let to_balance = self.balance(token, to);
+        vec![
+            self.set_balance(token, from, from_balance - amount),
+            self.set_balance(token, to, to_balance + amount),
+        ]
+    }
+
+    ///
+    /// The constant product swap: the token balances and the pair reserves and prices.
+    ///
+    pub fn uniswap_swap(&mut self) -> Vec<StorageTransition> {
+        if self.pools.is_empty() {
+            return Vec::new();
+        }
+        let pool_index = self.rng.popular(self.pools.len());
+        let trader = self.accounts[self.rng.popular(self.accounts.len())];
+        let zero_for_one = self.rng.below(2) == 0;
+
+        let pool = &self.pools[pool_index];
+        let (token_in, token_out, reserve_in, reserve_out) = if zero_for_one {
+            (pool.token0, pool.token1, pool.reserve0, pool.reserve1)
+        } else {
+            (pool.token1, pool.token0, pool.reserve1, pool.reserve0)
+        };
+        let pair = pool.address;
+        let trader_in = self.balance(token_in, trader);
+        let amount_in = self.rng.between(1, reserve_in / 100).min(trader_in);
+        // `getAmountOut` with the 0.3% fee, the products overflow u128, the f64 is exact enough.
+        let amount_in_with_fee = amount_in as f64 * 0.997;
+        let amount_out = (amount_in_with_fee * reserve_out as f64 / (reserve_in as f64 + amount_in_with_fee)) as u128;
+
+        // The pair balances are the reserves after the swap.
+        let trader_out = self.balance(token_out, trader);
+        let mut transitions = vec![
+            self.set_balance(token_in, trader, trader_in - amount_in),
+            self.set_balance(token_in, pair, reserve_in + amount_in),
+            self.set_balance(token_out, pair, reserve_out - amount_out),
+            self.set_balance(token_out, trader, trader_out + amount_out),
+        ];
+
+        let price0_increment = self.rng.next() as u128;
+        let price1_increment = self.rng.next() as u128;
+        let timestamp = self.timestamp;
+        let pool = &mut self.pools[pool_index];
+        if zero_for_one {
+            pool.reserve0 += amount_in;
+            pool.reserve1 -= amount_out;
+        } else {
+            pool.reserve1 += amount_in;
+            pool.reserve0 -= amount_out;
+        }
+        pool.price0_cumulative = pool.price0_cumulative.wrapping_add(price0_increment);
+        pool.price1_cumulative = pool.price1_cumulative.wrapping_add(price1_increment);
+
+        // reserve0 | reserve1 << 112 | blockTimestampLast << 224
+        let mut reserves = word_from_u128(pool.reserve0);
+        reserves[4..18].copy_from_slice(&pool.reserve1.to_be_bytes()[2..]);
+        reserves[..4].copy_from_slice(&(timestamp as u32).to_be_bytes());
+
+        let pair = pool.address;
+        transitions.push(transition(pair, word_from_u64(PAIR_RESERVES_SLOT), reserves, None));
+        transitions.push(transition(pair, word_from_u64(PAIR_PRICE0_CUMULATIVE_SLOT), word_from_u128(pool.price0_cumulative), None));
+        transitions.push(transition(pair, word_from_u64(PAIR_PRICE1_CUMULATIVE_SLOT), word_from_u128(pool.price1_cumulative), None));
+        transitions
+    }
+
+    ///
+    /// The ERC721Enumerable mint of the next token id.
+    ///
+    pub fn nft_mint(&mut self) -> Vec<StorageTransition> {
+        if self.collections.is_empty() {
+            return Vec::new();
+        }
+        let collection_index = self.rng.popular(self.collections.len());
+        let owner_index = self.rng.popular(self.accounts.len());
+        let owner = self.accounts[owner_index];
+        let (address, collection) = &mut self.collections[collection_index];
+        let address = *address;
+
+        let token_id = collection.total_supply;
+        let index = collection.total_supply;
+        let owned = collection.balances.entry(owner_index).or_default();
+        let owned_index = *owned;
+        *owned += 1;
+        collection.total_supply += 1;
+
+        let token = word_from_u64(token_id);
+        let owner_word = word_from_address(&owner);
+        let owned_tokens = mapping_slot(&owner_word, NFT_OWNED_TOKENS_SLOT);
+        let all_tokens = word_from_u64(NFT_ALL_TOKENS_SLOT);
+        let mut transitions = vec![
+            transition(address, mapping_slot(&token, NFT_OWNERS_SLOT), owner_word, None),
+            transition(address, mapping_slot(&owner_word, NFT_BALANCES_SLOT), word_from_u64(owned_index + 1), None),
+            transition(address, keccak(&[&word_from_u64(owned_index), &owned_tokens]), token, None),
+            transition(address, mapping_slot(&token, NFT_OWNED_TOKENS_INDEX_SLOT), word_from_u64(owned_index), None),
+            transition(address, all_tokens, word_from_u64(index + 1), None),
+            transition(
+                address,
+                slot_from_preimage_and_offset(all_tokens, word_from_u64(index)),
+                token,
+                Some((all_tokens, word_from_u64(index))),
+            ),
+            transition(address, mapping_slot(&token, NFT_ALL_TOKENS_INDEX_SLOT), word_from_u64(index), None),
+        ];
+        // The zero indexes don't change the empty slots.
+        transitions.retain(|transition| transition.value != [0; KEY_VALUE_SIZE]);
+        transitions
+    }
+
+    ///
+    /// The EIP-1967 admin or implementation change of the proxy.
+    ///
+    pub fn proxy_admin_write(&mut self) -> Vec<StorageTransition> {
+        if self.proxies.is_empty() {
+            return Vec::new();
+        }
+        let proxy = self.proxies[self.rng.popular(self.proxies.len())];
+        let label = if self.rng.below(2) == 0 { "eip1967.proxy.admin" } else { "eip1967.proxy.implementation" };
+        let mut slot: Word = sha3::Keccak256::digest(label.as_bytes()).into();
+        // The slot is `keccak256(label) - 1`.
+        for byte in slot.iter_mut().rev() {
+            let borrow = *byte == 0;
+            *byte = byte.wrapping_sub(1);
+            if !borrow {
+                break;
+            }
+        }
+
+        let mut value = [0u8; ADDRESS_SIZE];
+        for byte in value.iter_mut() {
+            *byte = self.rng.next() as u8;
+        }
+        vec![transition(proxy, slot, word_from_address(&value), None)]
+    }
+
+    ///
+    /// The token balance, the unseen holders get the random initial balance.
+    ///
+    fn balance(&mut self, token: usize, holder: Address) -> u128 {
+        if let Some(balance) = self.balances.get(&(token, holder)) {
+            return *balance;
+        }
+        let balance = self.rng.between(1 << 40, 1 << 80);
+        self.balances.insert((token, holder), balance);
+        balance
+    }
+
+    fn set_balance(&mut self, token: usize, holder: Address, balance: u128) -> StorageTransition {
+        self.balances.insert((token, holder), balance);
+        let key = mapping_slot(&word_from_address(&holder), ERC20_BALANCES_SLOT);
+        transition(self.tokens[token], key, word_from_u128(balance), None)
+    }
+}
+
+///
+/// The splitmix64 generator, the same numbers on every platform.
+///
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn between(&mut self, low: u128, high: u128) -> u128 {
+        let random = (self.next() as u128) << 64 | self.next() as u128;
+        low + random % (high - low).max(1)
+    }
+
+    ///
+    /// The index skewed to the small ones, the first items are the popular ones.
+    ///
+    fn popular(&mut self, bound: usize) -> usize {
+        self.below(bound).min(self.below(bound))
+    }
+}
+
+fn transition(address: Address, key: Word, value: Word, meta: Option<(Word, Word)>) -> StorageTransition {
+    StorageTransition { address, key, value, meta }
+}
+
+///
+/// The slot of the mapping value: `keccak256(key . slot)`.
+///
+fn mapping_slot(key: &Word, slot: u64) -> Word {
+    keccak(&[key, &word_from_u64(slot)])
+}
+
+fn word_from_u64(value: u64) -> Word {
+    word_from_u128(value as u128)
+}
+
+fn word_from_u128(value: u128) -> Word {
+    let mut word = [0u8; KEY_VALUE_SIZE];
+    word[KEY_VALUE_SIZE - 16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn word_from_address(address: &Address) -> Word {
+    let mut word = [0u8; KEY_VALUE_SIZE];
+    word[KEY_VALUE_SIZE - ADDRESS_SIZE..].copy_from_slice(address);
+    word
+}